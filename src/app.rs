@@ -1,13 +1,158 @@
+use std::ffi::OsString;
+use std::path::Path;
 use std::process::{Command, exit};
 
-use crate::builder;
+use crate::builder::{self, TargetKind};
+use crate::depman;
+use crate::depman::lockfile::{self, Change};
+use crate::depman::package::Packaged;
+use crate::depman::registry::{self, Registry};
+use crate::depman::resolver::{Network, ResolveOptions, Unlock, resolve_and_fetch};
+use crate::depman::tree::{self, TreeFormat};
 use crate::io;
+use crate::platform;
+use crate::profile;
+use crate::tester::report::{self, ReportFormat};
+use crate::tester::{self, TestProgram};
+use std::time::{Duration, Instant};
 
-pub fn run() {
+/// Picks the number of parallel compile jobs: the command line wins, then
+/// the user config, then however many cores the machine has.
+fn resolve_jobs(jobs: Option<usize>) -> usize {
+    jobs.or(io::load_user_config().build.jobs)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+        .max(1)
+}
+
+/// Resolver settings for this run. `--offline` or `[net] offline = true` in
+/// the user config keeps everything to the package cache.
+pub fn resolve_options(
+    offline: bool,
+    features: &[String],
+    no_default_features: bool,
+    target: Option<&str>,
+) -> ResolveOptions {
+    ResolveOptions {
+        offline: offline || io::load_user_config().net.offline,
+        features: features.to_vec(),
+        no_default_features,
+        target: target.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+/// Loads Seastar.toml with the `[target]` tables for the build's platform
+/// merged in. The returned options name that platform's triple, so the
+/// dependencies are configured for the same one.
+fn load_config(resolve: &ResolveOptions) -> (io::Config, ResolveOptions) {
+    let mut config = load_root_config();
+    let platform = platform::detect(resolve.target.as_deref(), &config.package.compiler);
+    platform::apply(&mut config, &platform);
+    let resolve = ResolveOptions {
+        target: Some(platform.triple),
+        ..resolve.clone()
+    };
+    (config, resolve)
+}
+
+/// The project's own Seastar.toml. `[build-dependencies]` aren't supported,
+/// and here, unlike in a dependency, they're the user's to remove.
+fn load_root_config() -> io::Config {
     let config = io::load_config("Seastar.toml");
-    let output_path = builder::build(&config, "src", "target/obj", "target");
+    if !config.build_dependencies.is_empty() {
+        eprintln!(
+            "Error: [build-dependencies] aren't supported yet, since Seastar has no build scripts\nMove them to [dependencies] or remove them."
+        );
+        exit(1);
+    }
+    config
+}
+
+/// Like `load_config`, but takes in every optional dependency and the
+/// `[target]` dependencies of every platform, for commands that have to
+/// cover any build.
+fn load_config_for_any_build(resolve: &ResolveOptions) -> (io::Config, ResolveOptions) {
+    let (mut config, resolve) = load_config(resolve);
+    let platform = platform::detect(resolve.target.as_deref(), &config.package.compiler);
+    platform::include_inactive(&mut config, &platform);
+    let resolve = ResolveOptions {
+        all_optional: true,
+        all_targets: true,
+        ..resolve
+    };
+    (config, resolve)
+}
+
+/// Environment that lets programs find shared libraries built into
+/// `lib_dir`, even if they were linked without an rpath.
+fn library_path_env(lib_dir: &Path) -> Vec<(OsString, OsString)> {
+    let lib_dir = std::fs::canonicalize(lib_dir).unwrap_or_else(|_| lib_dir.to_path_buf());
+    let mut paths = vec![lib_dir];
+    if let Some(existing) = std::env::var_os("LD_LIBRARY_PATH") {
+        paths.extend(std::env::split_paths(&existing));
+    }
+    match std::env::join_paths(paths) {
+        Ok(joined) => vec![("LD_LIBRARY_PATH".into(), joined)],
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn run(
+    profile_name: &str,
+    jobs: Option<usize>,
+    bin: Option<&str>,
+    example: Option<&str>,
+    resolve: &ResolveOptions,
+) {
+    let (config, resolve) = load_config(resolve);
+    let resolve = &resolve;
+    let profile = profile::resolve(&config, profile_name);
+    let artifacts = builder::build(
+        &config,
+        &profile,
+        "src",
+        resolve_jobs(jobs),
+        resolve,
+        if example.is_some() {
+            &[TargetKind::Example]
+        } else {
+            &[]
+        },
+    );
+
+    let (kind, wanted) = match (bin, example) {
+        (_, Some(name)) => (TargetKind::Example, Some(name)),
+        (name, None) => (TargetKind::Bin, name),
+    };
+    let candidates: Vec<_> = artifacts
+        .iter()
+        .filter(|a| a.kind == kind && wanted.is_none_or(|name| a.name == name))
+        .collect();
+
+    let artifact = match candidates.as_slice() {
+        [artifact] => artifact,
+        [] => {
+            match wanted {
+                Some(name) => eprintln!("Error: no {} named '{}'", kind.describe(), name),
+                None => eprintln!("Error: this package has no binary to run"),
+            }
+            exit(1);
+        }
+        _ => {
+            eprintln!("Error: this package has several binaries; pick one with --bin <name>:");
+            for artifact in candidates {
+                eprintln!("    {}", artifact.name);
+            }
+            exit(1);
+        }
+    };
 
-    let status = Command::new(output_path)
+    let status = Command::new(&artifact.path)
+        .envs(library_path_env(&profile.target_dir()))
         .status()
         .expect("Failed to run program. Maybe try running it manually?");
     if !status.success() {
@@ -15,25 +160,330 @@ pub fn run() {
     }
 }
 
-pub fn build() {
-    let config = io::load_config("Seastar.toml");
+pub fn build(profile_name: &str, jobs: Option<usize>, examples: bool, resolve: &ResolveOptions) {
+    let (config, resolve) = load_config(resolve);
+    let resolve = &resolve;
+    let profile = profile::resolve(&config, profile_name);
 
-    let output_path = builder::build(&config, "src", "target/obj", "target");
-    println!("Successfully built to {}.", output_path);
+    let extra: &[TargetKind] = if examples {
+        &[TargetKind::Example]
+    } else {
+        &[]
+    };
+    let artifacts = builder::build(&config, &profile, "src", resolve_jobs(jobs), resolve, extra);
+    for artifact in artifacts {
+        println!(
+            "Successfully built {} '{}' to {}.",
+            artifact.kind.describe(),
+            artifact.name,
+            artifact.path.display()
+        );
+    }
 }
 
-pub fn clean() {
-    let target_path = "target";
-    let deps_path = "deps";
+/// Builds everything in `tests/` and runs it. Exits with status 1 if any
+/// test fails or times out. With a `format`, a report is written to
+/// `output`, or to stdout in place of the usual summary.
+pub fn test(
+    profile_name: &str,
+    jobs: Option<usize>,
+    filter: Option<&str>,
+    timeout: Duration,
+    format: Option<ReportFormat>,
+    output: Option<&Path>,
+    resolve: &ResolveOptions,
+) {
+    let (config, resolve) = load_config(resolve);
+    let resolve = &resolve;
+    let profile = profile::resolve(&config, profile_name);
+    let jobs = resolve_jobs(jobs);
 
-    if std::fs::exists("target").unwrap_or(false) {
-        if std::fs::remove_dir_all(target_path).is_err() {
-            println!("Failed to clean target directory: {}", target_path);
+    let artifacts = builder::build(&config, &profile, "src", jobs, resolve, &[TargetKind::Test]);
+    let programs: Vec<TestProgram> = artifacts
+        .into_iter()
+        .filter(|a| a.kind == TargetKind::Test)
+        .map(|a| TestProgram {
+            name: a.name,
+            path: a.path,
+            source: a.entry.unwrap_or_default(),
+        })
+        .collect();
+
+    // A requested report is still written, just empty, so CI always finds
+    // a document in the format it asked for.
+    if programs.is_empty() {
+        eprintln!("No tests found in tests/");
+        if format.is_none() {
+            return;
         }
     }
-    if std::fs::exists("deps").unwrap_or(false) {
-        if std::fs::remove_dir_all(deps_path).is_err() {
-            println!("Failed to clean deps directory: {}", deps_path);
+
+    let report_to_stdout = format.is_some() && output.is_none();
+    let start = Instant::now();
+    let results = tester::run(
+        &programs,
+        filter,
+        jobs,
+        timeout,
+        &library_path_env(&profile.target_dir()),
+        !report_to_stdout,
+    );
+    let elapsed = start.elapsed();
+    let all_passed = results
+        .iter()
+        .all(|r| r.status == tester::TestStatus::Passed);
+
+    if let Some(format) = format {
+        let report = report::render(format, &results, elapsed);
+        match output {
+            Some(path) => {
+                if let Err(e) = std::fs::write(path, report) {
+                    eprintln!("Error: failed to write {}: {}", path.display(), e);
+                    exit(1);
+                }
+            }
+            None => print!("{}", report),
         }
     }
+    if !report_to_stdout {
+        tester::print_summary(&results, elapsed);
+    }
+
+    if !all_passed {
+        exit(1);
+    }
+}
+
+pub fn compdb(profile_name: &str, include_deps: bool, resolve: &ResolveOptions) {
+    let (config, resolve) = load_config(resolve);
+    let resolve = &resolve;
+    let profile = profile::resolve(&config, profile_name);
+    builder::write_compdb(&config, &profile, "src", include_deps, resolve);
+    println!("Wrote compile_commands.json");
+}
+
+/// Re-resolves the named dependencies (or all of them) and rewrites
+/// Seastar.lock with whatever they resolve to now. Offline, that means the
+/// newest versions already in the package cache.
+pub fn update(packages: &[String], resolve: &ResolveOptions) {
+    let (config, resolve) = load_config(resolve);
+    let resolve = &resolve;
+    let lock_path = Path::new(lockfile::LOCKFILE_PATH);
+    let lock = lockfile::load(lock_path);
+    for name in packages {
+        let known = config.dependencies.contains_key(name)
+            || config.dev_dependencies.contains_key(name)
+            || lock.packages.iter().any(|p| &p.name == name);
+        if !known {
+            eprintln!("Error: '{}' is not a dependency of this package", name);
+            exit(1);
+        }
+    }
+
+    let unlock = if packages.is_empty() {
+        Unlock::All
+    } else {
+        Unlock::Packages(packages.to_vec())
+    };
+    resolve_and_fetch(
+        &config,
+        "deps/",
+        &ResolveOptions {
+            unlock,
+            ..resolve.clone()
+        },
+    );
+
+    let new_lock = lockfile::load(lock_path);
+    let changes = new_lock.changes_since(&lock);
+    if changes.is_empty() {
+        println!("Dependencies are already up to date");
+        return;
+    }
+    for change in changes {
+        match change {
+            Change::Added(p) => println!("    Adding {} {}", p.name, p.short_pin()),
+            Change::Removed(p) => println!("  Removing {} {}", p.name, p.short_pin()),
+            Change::Updated { old, new } if old.source != new.source => println!(
+                "  Updating {} {} ({}) -> {} ({})",
+                new.name,
+                old.short_pin(),
+                old.source,
+                new.short_pin(),
+                new.source
+            ),
+            Change::Updated { old, new } => println!(
+                "  Updating {} {} -> {}",
+                new.name,
+                old.short_pin(),
+                new.short_pin()
+            ),
+        }
+    }
+}
+
+/// Prints the resolved dependency graph, fetching anything missing first.
+pub fn tree(invert: Option<&str>, duplicates: bool, format: TreeFormat, resolve: &ResolveOptions) {
+    let (config, resolve) = load_config(resolve);
+    let resolve = &resolve;
+    let graph = resolve_and_fetch(&config, "deps/", resolve);
+    let root_deps = &graph.root_deps;
+
+    let output = match (format, invert) {
+        (TreeFormat::Json, None) if !duplicates => {
+            format!("{}\n", tree::render_json(&config, &graph, root_deps))
+        }
+        (TreeFormat::Json, _) => {
+            eprintln!("Error: --format json always prints the whole graph");
+            exit(1);
+        }
+        (TreeFormat::Text, Some(name)) => tree::render_inverted(&config, &graph, root_deps, name)
+            .unwrap_or_else(|| {
+                eprintln!("Error: '{}' is not in the dependency graph", name);
+                exit(1);
+            }),
+        (TreeFormat::Text, None) if duplicates => {
+            tree::render_duplicates(&config, &graph, root_deps)
+        }
+        (TreeFormat::Text, None) => tree::render(&config, &graph, root_deps),
+    };
+    print!("{}", output);
+}
+
+/// Downloads every package any build could need, optional ones and other
+/// platforms' included, into the package cache and deps/, so later commands
+/// can run with `--offline` whatever features or target they pick.
+pub fn fetch(resolve: &ResolveOptions) {
+    let (config, resolve) = load_config_for_any_build(resolve);
+    let graph = resolve_and_fetch(&config, "deps/", &resolve);
+    match graph.nodes.len() {
+        1 => println!("Fetched 1 package"),
+        n => println!("Fetched {} packages", n),
+    }
+}
+
+/// Copies the whole resolved graph, optional dependencies and other
+/// platforms' included, into vendor/ and redirects its sources there, so
+/// builds no longer need the package cache.
+pub fn vendor(resolve: &ResolveOptions) {
+    let (config, resolve) = load_config_for_any_build(resolve);
+    let graph = resolve_and_fetch(
+        &config,
+        "deps/",
+        &ResolveOptions {
+            ignore_vendor: true,
+            ..resolve
+        },
+    );
+    let vendored = depman::vendor::vendor(&graph, "deps/");
+    println!(
+        "Vendored {} packages into {}/ (sources redirected in {}/config.toml)",
+        vendored.source.len(),
+        depman::vendor::VENDOR_DIR,
+        depman::vendor::VENDOR_DIR
+    );
+}
+
+/// Builds the package archive and reports where it went.
+pub fn package() -> Packaged {
+    let config = load_root_config();
+    let packaged = depman::package::package(&config);
+    println!(
+        "Packaged {} {} to {}\nsha256: {}",
+        packaged.name,
+        packaged.version,
+        packaged.archive.display(),
+        packaged.archive_checksum
+    );
+    packaged
+}
+
+/// Packages this library and adds it to the registry at `location`, or the
+/// configured one.
+pub fn publish(location: Option<&str>) {
+    let Some(location) = location.map(String::from).or_else(registry::configured) else {
+        eprintln!(
+            "Error: no registry given; pass --registry <path> or set `[registry] index` in ~/.seastar/config.toml"
+        );
+        exit(1);
+    };
+
+    let packaged = package();
+    if let Err(e) = Registry::publish(&location, &packaged) {
+        eprintln!("Error: failed to publish: {}", e);
+        exit(1);
+    }
+    println!(
+        "Published {} {} to {}",
+        packaged.name, packaged.version, location
+    );
+}
+
+/// Lists registry packages whose name or description contains `query`.
+pub fn search(query: &str, resolve: &ResolveOptions) {
+    let Some(index) = registry::configured() else {
+        eprintln!(
+            "Error: no registry configured; set `[registry] index = \"...\"` in ~/.seastar/config.toml"
+        );
+        exit(1);
+    };
+    let network = if resolve.offline {
+        Network::Offline
+    } else {
+        Network::Online
+    };
+    let registry = Registry::open(&index, network);
+
+    let query = query.to_lowercase();
+    let matches: Vec<_> = registry
+        .entries()
+        .into_iter()
+        .filter(|entry| {
+            entry.name.to_lowercase().contains(&query)
+                || entry
+                    .description
+                    .as_ref()
+                    .is_some_and(|d| d.to_lowercase().contains(&query))
+        })
+        .collect();
+
+    if matches.is_empty() {
+        println!("No packages matching '{}'", query);
+        return;
+    }
+    for entry in matches {
+        let version = entry
+            .latest()
+            .map(|v| v.version.to_string())
+            .unwrap_or_default();
+        match &entry.description {
+            Some(description) => {
+                println!("{} = \"{}\"    # {}", entry.name, version, description)
+            }
+            None => println!("{} = \"{}\"", entry.name, version),
+        }
+    }
+}
+
+/// Without a profile, removes all build output and fetched dependencies.
+/// With one, only that profile's output directory goes.
+pub fn clean(profile_name: Option<&str>) {
+    if let Some(profile_name) = profile_name {
+        let config = io::load_config("Seastar.toml");
+        let profile_dir = profile::resolve(&config, profile_name).target_dir();
+        if profile_dir.exists() && std::fs::remove_dir_all(&profile_dir).is_err() {
+            println!("Failed to clean directory: {}", profile_dir.display());
+        }
+        return;
+    }
+
+    let target_path = "target";
+    let deps_path = "deps";
+
+    if std::fs::exists("target").unwrap_or(false) && std::fs::remove_dir_all(target_path).is_err() {
+        println!("Failed to clean target directory: {}", target_path);
+    }
+    if std::fs::exists("deps").unwrap_or(false) && std::fs::remove_dir_all(deps_path).is_err() {
+        println!("Failed to clean deps directory: {}", deps_path);
+    }
 }
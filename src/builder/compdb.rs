@@ -0,0 +1,30 @@
+// Writes a `compile_commands.json` (the JSON compilation database used by
+// clangd and friends) so editors see the same include paths and flags that
+// Seastar passes to the compiler.
+
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+pub const COMPDB_PATH: &str = "compile_commands.json";
+
+#[derive(Debug, Serialize)]
+pub struct CompileCommand {
+    pub directory: String,
+    pub file: String,
+    pub arguments: Vec<String>,
+    pub output: String,
+}
+
+pub fn write(path: &Path, commands: &[CompileCommand]) {
+    let json =
+        serde_json::to_string_pretty(commands).expect("Failed to serialize compile commands");
+
+    // Leave the file alone when nothing changed so clangd doesn't reindex.
+    if fs::read_to_string(path).is_ok_and(|old| old == json) {
+        return;
+    }
+    if let Err(e) = fs::write(path, json) {
+        eprintln!("Warning: failed to write {}: {}", path.display(), e);
+    }
+}
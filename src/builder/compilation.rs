@@ -1,8 +1,13 @@
+use super::compdb::CompileCommand;
+use super::fingerprint;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeSet;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::time::SystemTime;
+use std::process::{Command, exit};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 pub struct LanguageBuilder<'a> {
     pub name: &'a str,
@@ -10,31 +15,143 @@ pub struct LanguageBuilder<'a> {
     pub compiler: &'a str,
     pub include_flag: Option<&'a str>, // e.g., "-I"
     pub compile_flags: &'a str,
+    /// The package's enabled features, which are part of every object's
+    /// fingerprint.
+    pub features: &'a BTreeSet<String>,
 }
 
-fn is_rebuild_required(src_file: &Path, obj_file: &Path) -> bool {
-    if !obj_file.exists() {
+/// Reads the make-style depfile written by `-MMD -MF` and returns every file
+/// the object was built from, the source itself included. `None` when the
+/// file is missing or doesn't parse.
+fn read_depfile(dep_file: &Path) -> Option<Vec<PathBuf>> {
+    parse_depfile(&fs::read_to_string(dep_file).ok()?)
+}
+
+fn parse_depfile(data: &str) -> Option<Vec<PathBuf>> {
+    let data = data.replace("\\\r\n", " ").replace("\\\n", " ");
+
+    let mut files = Vec::new();
+    for line in data.lines().filter(|line| !line.trim().is_empty()) {
+        // `targets: prerequisites`. Targets can't contain ": ", and a rule
+        // may have no prerequisites at all (`-MP` writes those for headers).
+        let (_, prereqs) = line
+            .split_once(": ")
+            .or_else(|| line.trim_end().strip_suffix(':').map(|t| (t, "")))?;
+
+        let mut current = String::new();
+        let mut chars = prereqs.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some(' ' | '#')) => {
+                    current.extend(chars.next());
+                }
+                '$' if chars.peek() == Some(&'$') => {
+                    current.push('$');
+                    chars.next();
+                }
+                c if c.is_whitespace() => {
+                    if !current.is_empty() {
+                        files.push(PathBuf::from(std::mem::take(&mut current)));
+                    }
+                }
+                c => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            files.push(PathBuf::from(current));
+        }
+    }
+    // Every object depends on at least its source, so an empty list means
+    // the file was cut short.
+    if files.is_empty() { None } else { Some(files) }
+}
+
+fn is_rebuild_required(src_file: &Path, obj_file: &Path, fingerprint: &str) -> bool {
+    if !fingerprint::matches(&fingerprint::path_for(obj_file, true), fingerprint) {
         return true;
     }
-    let src_meta = fs::metadata(src_file).ok();
-    let obj_meta = fs::metadata(obj_file).ok();
-    if let (Some(s_m), Some(o_m)) = (src_meta, obj_meta) {
-        let s_time = s_m.modified().unwrap_or(SystemTime::UNIX_EPOCH);
-        let o_time = o_m.modified().unwrap_or(SystemTime::UNIX_EPOCH);
-        return s_time > o_time;
+    let Some(o_time) = fs::metadata(obj_file).and_then(|m| m.modified()).ok() else {
+        return true;
+    };
+
+    // Without a readable depfile we can't know which headers were used, so
+    // play it safe and rebuild (this also covers objects from older Seastar
+    // versions).
+    let Some(inputs) = read_depfile(&obj_file.with_extension("d")) else {
+        return true;
+    };
+
+    std::iter::once(src_file.to_path_buf())
+        .chain(inputs)
+        .any(
+            |input| match fs::metadata(&input).and_then(|m| m.modified()) {
+                Ok(i_time) => i_time > o_time,
+                // A header that disappeared may have moved; let the compiler decide.
+                Err(_) => true,
+            },
+        )
+}
+
+pub fn matches_lang(lang: &LanguageBuilder, src_file: &Path) -> bool {
+    src_file.extension().is_some_and(|e| {
+        lang.extensions
+            .iter()
+            .any(|ext| e.eq_ignore_ascii_case(*ext))
+    })
+}
+
+/// Objects mirror the source layout, so `src/net/util.c` becomes
+/// `obj/net/util.c.o` and can't collide with `src/fs/util.c`.
+pub fn object_path(src_file: &Path, src_dir: &Path, obj_dir: &Path) -> PathBuf {
+    let rel = src_file
+        .strip_prefix(src_dir)
+        .unwrap_or_else(|_| Path::new(src_file.file_name().unwrap_or_default()));
+    let file_name = rel
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown");
+    obj_dir.join(rel).with_file_name(format!("{}.o", file_name))
+}
+
+/// Deletes objects (and their depfiles and fingerprints) in `obj_dir` that
+/// weren't produced by this build, e.g. because their source was removed.
+pub fn prune_stale_objects(obj_dir: &Path, keep: &[PathBuf]) {
+    let stale: Vec<PathBuf> = walkdir::WalkDir::new(obj_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "o"))
+        .filter(|p| !keep.contains(p))
+        .collect();
+
+    for obj in stale {
+        eprintln!("Removing stale object {}", obj.display());
+        let _ = fs::remove_file(obj.with_extension("d"));
+        let _ = fs::remove_file(fingerprint::path_for(&obj, true));
+        let _ = fs::remove_file(&obj);
     }
-    true
 }
 
 pub fn compile_files(
     lang: &LanguageBuilder,
     src_files: &[PathBuf],
+    src_dir: &Path,
     obj_dir: &Path,
     include_dirs: &[PathBuf],
+    jobs: usize,
 ) -> Vec<PathBuf> {
     fs::create_dir_all(obj_dir).expect("Failed to create object directory");
 
-    let pb = ProgressBar::new(src_files.len() as u64);
+    // Work out every object path up front so the returned list keeps the
+    // order of `src_files`, no matter which worker finishes first.
+    let units: Vec<(&PathBuf, PathBuf)> = src_files
+        .iter()
+        .filter(|src_file| matches_lang(lang, src_file))
+        .map(|src_file| (src_file, object_path(src_file, src_dir, obj_dir)))
+        .collect();
+
+    let pb = ProgressBar::new(units.len() as u64);
     pb.set_style(
         ProgressStyle::with_template(&format!(
             "[{{elapsed_precise}}] [{{bar:40.cyan/blue}}] {{pos}}/{{len}} ({}: {{msg}})",
@@ -44,65 +161,223 @@ pub fn compile_files(
         .progress_chars("=> "),
     );
 
-    let mut objects = vec![];
-    for src_file in src_files {
-        let stem = src_file
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("unknown");
-        let ext = src_file.extension().and_then(|s| s.to_str()).unwrap_or("");
-        let obj_name = format!("{}.{}.o", stem, ext);
-        let obj_path = obj_dir.join(obj_name);
-
-        let matches_lang = src_file.extension().is_some_and(|e| {
-            lang.extensions
-                .iter()
-                .any(|ext| e.eq_ignore_ascii_case(*ext))
-        });
-        if !matches_lang {
-            continue;
+    for (_, obj_path) in &units {
+        if let Some(parent) = obj_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create object directory");
         }
+    }
 
-        if is_rebuild_required(src_file, &obj_path) {
-            pb.set_message(format!("Compiling {}", src_file.display()));
-
-            let mut cmd = Command::new(lang.compiler);
-            let include_flags = include_dirs
-                .iter()
-                .filter(|p| p.exists())
-                .map(|p| {
-                    format!(
-                        "{}{}",
-                        lang.include_flag.unwrap_or("-I"),
-                        p.to_str().unwrap()
-                    )
-                })
-                .collect::<Vec<_>>();
-
-            for flag in include_flags {
-                cmd.arg(flag);
-            }
+    let compiler_identity = fingerprint::tool_identity(lang.compiler);
 
-            for flag in lang.compile_flags.split_whitespace() {
-                if !flag.is_empty() {
-                    cmd.arg(flag);
-                }
-            }
-            cmd.arg("-c").arg(src_file).arg("-o").arg(&obj_path);
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    let workers = jobs.clamp(1, units.len().max(1));
 
-            let status = cmd.status().expect("Failed to run compiler");
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    // Once anything has failed, let the running compilers
+                    // finish but don't start new ones.
+                    if !failed.lock().unwrap().is_empty() {
+                        break;
+                    }
+                    let Some((src_file, obj_path)) = units.get(next.fetch_add(1, Ordering::SeqCst))
+                    else {
+                        break;
+                    };
 
-            if !status.success() {
-                pb.finish_and_clear();
-                panic!("Compilation failed for {:?}", src_file);
-            }
-        } else {
-            pb.set_message(format!("Cached    {}", src_file.display()));
+                    let args = compile_args(lang, src_file, obj_path, include_dirs);
+                    let mut fp_inputs = args.clone();
+                    fp_inputs.extend(include_dirs.iter().map(|p| p.display().to_string()));
+                    fp_inputs.extend(lang.features.iter().map(|f| format!("feature:{}", f)));
+                    let fp = fingerprint::compute(&compiler_identity, &fp_inputs);
+
+                    if is_rebuild_required(src_file, obj_path, &fp) {
+                        pb.set_message(format!("Compiling {}", src_file.display()));
+                        if compile_one(lang, &args, &pb) {
+                            fingerprint::save(&fingerprint::path_for(obj_path, true), &fp);
+                        } else {
+                            failed.lock().unwrap().push(src_file.to_path_buf());
+                        }
+                    } else {
+                        pb.set_message(format!("Cached    {}", src_file.display()));
+                    }
+                    pb.inc(1);
+                }
+            });
         }
+    });
 
-        objects.push(obj_path);
-        pb.inc(1);
+    let failed = failed.into_inner().unwrap();
+    if !failed.is_empty() {
+        pb.finish_and_clear();
+        eprintln!("Error: {} compilation failed for:", lang.name);
+        for src_file in &failed {
+            eprintln!("    {}", src_file.display());
+        }
+        exit(1);
     }
+
     pb.finish_with_message("Compilation done");
-    objects
+    units.into_iter().map(|(_, obj_path)| obj_path).collect()
+}
+
+/// The exact commands `compile_files` runs for these sources, without
+/// running them.
+pub fn compile_commands(
+    lang: &LanguageBuilder,
+    src_files: &[PathBuf],
+    src_dir: &Path,
+    obj_dir: &Path,
+    include_dirs: &[PathBuf],
+) -> Vec<CompileCommand> {
+    let directory = std::env::current_dir()
+        .map(|d| d.display().to_string())
+        .unwrap_or_else(|_| ".".to_string());
+
+    src_files
+        .iter()
+        .filter(|src_file| matches_lang(lang, src_file))
+        .map(|src_file| {
+            let obj_path = object_path(src_file, src_dir, obj_dir);
+            let mut arguments = vec![lang.compiler.to_string()];
+            arguments.extend(compile_args(lang, src_file, &obj_path, include_dirs));
+            CompileCommand {
+                directory: directory.clone(),
+                file: src_file.display().to_string(),
+                arguments,
+                output: obj_path.display().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// The full argument list (minus the compiler itself) used to build one
+/// translation unit.
+fn compile_args(
+    lang: &LanguageBuilder,
+    src_file: &Path,
+    obj_path: &Path,
+    include_dirs: &[PathBuf],
+) -> Vec<String> {
+    let mut args = include_dirs
+        .iter()
+        .filter(|p| p.exists())
+        .map(|p| {
+            format!(
+                "{}{}",
+                lang.include_flag.unwrap_or("-I"),
+                p.to_str().unwrap()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    args.extend(lang.compile_flags.split_whitespace().map(String::from));
+    // Let the compiler tell us which headers this unit includes, so editing
+    // one of them triggers a rebuild next time.
+    args.push("-MMD".into());
+    args.push("-MF".into());
+    args.push(obj_path.with_extension("d").display().to_string());
+    args.push("-c".into());
+    args.push(src_file.display().to_string());
+    args.push("-o".into());
+    args.push(obj_path.display().to_string());
+    args
+}
+
+/// Runs the compiler for a single translation unit. Output is captured and
+/// printed above the progress bar so parallel jobs don't interleave.
+fn compile_one(lang: &LanguageBuilder, args: &[String], pb: &ProgressBar) -> bool {
+    let mut cmd = Command::new(lang.compiler);
+    cmd.args(args);
+
+    let output = cmd.output().expect("Failed to run compiler");
+    if !output.stdout.is_empty() || !output.stderr.is_empty() {
+        pb.suspend(|| {
+            eprint!("{}", String::from_utf8_lossy(&output.stdout));
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        });
+    }
+
+    output.status.success()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(files: &[&str]) -> Option<Vec<PathBuf>> {
+        Some(files.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn depfile_single_line() {
+        assert_eq!(
+            parse_depfile("obj/main.c.o: src/main.c include/a.h\n"),
+            paths(&["src/main.c", "include/a.h"])
+        );
+    }
+
+    #[test]
+    fn depfile_continuation_lines() {
+        let data = "obj/main.c.o: src/main.c \\\n include/a.h \\\r\n  include/b.h\n";
+        assert_eq!(
+            parse_depfile(data),
+            paths(&["src/main.c", "include/a.h", "include/b.h"])
+        );
+    }
+
+    #[test]
+    fn depfile_escaped_characters() {
+        let data = "obj/a\\ b.c.o: src/a\\ b.c include/c\\#1.h include/$$x.h\n";
+        assert_eq!(
+            parse_depfile(data),
+            paths(&["src/a b.c", "include/c#1.h", "include/$x.h"])
+        );
+    }
+
+    #[test]
+    fn depfile_multiple_targets_and_rules() {
+        let data = "obj/a.o obj/a.d: src/a.c include/a.h\n\ninclude/a.h:\n";
+        assert_eq!(parse_depfile(data), paths(&["src/a.c", "include/a.h"]));
+    }
+
+    #[test]
+    fn depfile_corrupt_or_empty() {
+        assert_eq!(parse_depfile(""), None);
+        assert_eq!(parse_depfile("obj/a.o: \n"), None);
+        assert_eq!(parse_depfile("obj/a.o src/a.c\n"), None);
+        assert_eq!(parse_depfile("obj/a.o: src/a.c\ngarbage\n"), None);
+    }
+
+    #[test]
+    fn rebuild_when_depfile_is_corrupt() {
+        let dir = std::env::temp_dir().join(format!("seastar-test-rebuild-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("a.c");
+        let obj = dir.join("a.c.o");
+        fs::write(&src, "int a;").unwrap();
+        fs::write(&obj, "").unwrap();
+        fingerprint::save(&fingerprint::path_for(&obj, true), "fp");
+
+        fs::write(
+            obj.with_extension("d"),
+            format!("{}: {}\n", obj.display(), src.display()),
+        )
+        .unwrap();
+        assert!(!is_rebuild_required(&src, &obj, "fp"));
+
+        fs::write(obj.with_extension("d"), format!("{}: \n", obj.display())).unwrap();
+        assert!(is_rebuild_required(&src, &obj, "fp"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn depfile_missing() {
+        let missing = std::env::temp_dir().join("seastar-test-missing.d");
+        let _ = fs::remove_file(&missing);
+        assert_eq!(read_depfile(&missing), None);
+    }
 }
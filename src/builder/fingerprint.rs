@@ -0,0 +1,70 @@
+// Fingerprints record *how* an output was produced (which compiler, which
+// version of it, and the exact arguments), so that changing any of those
+// forces a rebuild even when no file timestamps moved.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Finds the executable `Command::new(program)` would run, falling back to
+/// the name itself when it can't be found on `PATH`.
+fn resolve_program(program: &str) -> String {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        return fs::canonicalize(program)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| program.to_string());
+    }
+    std::env::var_os("PATH")
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(program))
+                .find(|candidate| candidate.is_file())
+        })
+        .map(|p| fs::canonicalize(&p).unwrap_or(p).display().to_string())
+        .unwrap_or_else(|| program.to_string())
+}
+
+/// Resolved path plus `--version` output of a compiler, linker or archiver.
+pub fn tool_identity(program: &str) -> String {
+    let version = Command::new(program)
+        .arg("--version")
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+        .unwrap_or_default();
+    format!("{}\n{}", resolve_program(program), version.trim())
+}
+
+pub fn compute<S: AsRef<str>>(tool: &str, args: &[S]) -> String {
+    let mut data = String::from(tool);
+    for arg in args {
+        data.push('\0');
+        data.push_str(arg.as_ref());
+    }
+    format!("{:x}", md5::compute(data))
+}
+
+/// Where the fingerprint for `output` lives: `foo.c.o` -> `foo.c.fp`,
+/// and for linked artifacts `target/app` -> `target/app.fp`.
+pub fn path_for(output: &Path, is_object: bool) -> PathBuf {
+    if is_object {
+        output.with_extension("fp")
+    } else {
+        let mut name = output.as_os_str().to_owned();
+        name.push(".fp");
+        PathBuf::from(name)
+    }
+}
+
+pub fn matches(fp_path: &Path, fingerprint: &str) -> bool {
+    fs::read_to_string(fp_path).is_ok_and(|stored| stored.trim() == fingerprint)
+}
+
+pub fn save(fp_path: &Path, fingerprint: &str) {
+    if let Err(e) = fs::write(fp_path, fingerprint) {
+        eprintln!(
+            "Warning: failed to write fingerprint {}: {}",
+            fp_path.display(),
+            e
+        );
+    }
+}
@@ -1,40 +1,61 @@
+use super::fingerprint;
 use indicatif::ProgressBar;
-use std::{path::PathBuf, process::Command, time::Duration};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, exit},
+    time::Duration,
+};
+
+/// Linking is skipped when the artifact exists, was produced by the same
+/// command, and is newer than every object/library that goes into it.
+fn is_relink_required(objects: &[PathBuf], output: &Path, fingerprint: &str) -> bool {
+    if !fingerprint::matches(&fingerprint::path_for(output, false), fingerprint) {
+        return true;
+    }
+    let Ok(out_time) = fs::metadata(output).and_then(|m| m.modified()) else {
+        return true;
+    };
+    objects.iter().any(|obj| {
+        fs::metadata(obj)
+            .and_then(|m| m.modified())
+            .map_or(true, |t| t > out_time)
+    })
+}
+
+pub enum LinkKind {
+    Executable,
+    StaticLib,
+    /// A shared library; `soname` is recorded with `-Wl,-soname`.
+    SharedLib {
+        soname: String,
+    },
+}
 
 pub fn link_objects(
     compiler: &str,
     objects: &[PathBuf],
     output: &PathBuf,
-    is_library: &bool,
+    kind: &LinkKind,
     link_flags: &str,
 ) {
-    let pb = ProgressBar::new_spinner();
-    pb.set_message("Linking...");
-    pb.enable_steady_tick(Duration::new(0, 50_000_000));
-
-    if *is_library {
-        let mut cmd = Command::new("ar");
-        cmd.arg("rcs");
-        cmd.arg(output);
-
+    let (tool, args) = if let LinkKind::StaticLib = kind {
+        let mut args = vec!["rcs".to_string(), output.to_string_lossy().into_owned()];
         for obj in objects {
-            cmd.arg(obj);
-        }
-
-        let status = cmd.status().expect("Failed to run linker");
-        pb.finish_with_message("Static library created");
-
-        if !status.success() {
-            panic!("Static linking failed");
+            args.push(obj.to_string_lossy().into_owned());
         }
+        ("ar", args)
     } else {
-        let mut cmd = Command::new(compiler);
         let mut did_see_lib = false;
         // Insert start-group before first static library, and end-group after.
         // Not really sure why this is needed, but it removed the link errors.
         // TODO: instead of doing it this way, we should pass libraries as a
         // separate argument for clarity.
         let mut args = Vec::new();
+        if let LinkKind::SharedLib { soname } = kind {
+            args.push("-shared".into());
+            args.push(format!("-Wl,-soname,{}", soname));
+        }
         for obj in objects {
             let is_a = obj.extension().map(|e| e == "a").unwrap_or(false);
             if is_a && !did_see_lib {
@@ -46,21 +67,95 @@ pub fn link_objects(
         if did_see_lib {
             args.push("-Wl,--end-group".into());
         }
-        for arg in &args {
-            cmd.arg(arg);
-        }
         for flag in link_flags.split_whitespace() {
             if !flag.is_empty() {
-                cmd.arg(flag);
+                args.push(flag.to_string());
             }
         }
-        cmd.arg("-o").arg(output);
+        args.push("-o".into());
+        args.push(output.to_string_lossy().into_owned());
+        (compiler, args)
+    };
 
-        let status = cmd.status().expect("Failed to run linker");
-        pb.finish_with_message("Linking complete");
+    let fp = fingerprint::compute(&fingerprint::tool_identity(tool), &args);
+    if !is_relink_required(objects, output, &fp) {
+        return;
+    }
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_message("Linking...");
+    pb.enable_steady_tick(Duration::new(0, 50_000_000));
+
+    if let LinkKind::StaticLib = kind {
+        // `ar rcs` only adds and replaces members, so start from scratch to
+        // drop objects that are no longer part of the library.
+        let _ = fs::remove_file(output);
+    }
+
+    let output_result = Command::new(tool).args(&args).output();
+    let what = match kind {
+        LinkKind::Executable => "Linking",
+        LinkKind::StaticLib => "Static linking",
+        LinkKind::SharedLib { .. } => "Shared linking",
+    };
+    let linked = match output_result {
+        Ok(linked) => linked,
+        Err(e) => {
+            pb.finish_and_clear();
+            eprintln!("Error: failed to run {}: {}", tool, e);
+            exit(1);
+        }
+    };
+    if !linked.stdout.is_empty() || !linked.stderr.is_empty() {
+        pb.suspend(|| {
+            eprint!("{}", String::from_utf8_lossy(&linked.stdout));
+            eprint!("{}", String::from_utf8_lossy(&linked.stderr));
+        });
+    }
+    if !linked.status.success() {
+        pb.finish_and_clear();
+        eprintln!("Error: {} failed for {}", what, output.display());
+        exit(1);
+    }
+    pb.finish_with_message(match kind {
+        LinkKind::Executable => "Linking complete",
+        LinkKind::StaticLib => "Static library created",
+        LinkKind::SharedLib { .. } => "Shared library created",
+    });
+
+    fingerprint::save(&fingerprint::path_for(output, false), &fp);
+}
+
+/// Points each name in `links` at the next, more specific one, ending at
+/// `output`: `libfoo.so -> libfoo.so.1 -> libfoo.so.1.2.3`.
+pub fn create_soname_links(output: &Path, links: &[String]) {
+    let Some(dir) = output.parent() else {
+        return;
+    };
+    let mut target = output
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
 
-        if !status.success() {
-            panic!("Linking failed");
+    for link in links.iter().rev() {
+        if *link == target {
+            continue;
+        }
+        let link_path = dir.join(link);
+        if fs::read_link(&link_path).is_ok_and(|existing| existing == Path::new(&target)) {
+            target = link.clone();
+            continue;
+        }
+        let _ = fs::remove_file(&link_path);
+        #[cfg(unix)]
+        if let Err(e) = std::os::unix::fs::symlink(&target, &link_path) {
+            eprintln!(
+                "Error: failed to create symlink {}: {}",
+                link_path.display(),
+                e
+            );
+            exit(1);
         }
+        target = link.clone();
     }
 }
@@ -1,29 +1,193 @@
+mod compdb;
 mod compilation;
+mod fingerprint;
 mod linking;
+mod targets;
 
 use crate::{
     depman::{
         self,
-        resolver::{Dep, parse_deps, resolve_and_fetch},
+        features::Features,
+        resolver::{DepGraph, DepKind, DepNode, ResolveOptions, resolve_and_fetch, root_features},
     },
     io,
+    profile::Profile,
 };
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::exit;
 
+use compdb::CompileCommand;
 use compilation::LanguageBuilder;
+use linking::LinkKind;
+use targets::Target;
+pub use targets::TargetKind;
 
-fn build_deps(deps: &[Dep]) -> Vec<PathBuf> {
-    let graph = resolve_and_fetch(&deps, "deps/");
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cpp", "cc", "cxx", "c++"];
 
-    let mut dep_static_libs = Vec::new();
+/// The C and C++ toolchains a package's manifest asks for, with the flags
+/// to compile it with.
+fn languages<'a>(
+    config: &'a io::Config,
+    c_flags: &'a str,
+    cpp_flags: &'a str,
+    features: &'a Features,
+) -> [LanguageBuilder<'a>; 2] {
+    [
+        LanguageBuilder {
+            name: "C",
+            extensions: &["c"],
+            compiler: &config.package.compiler,
+            include_flag: Some("-I"),
+            compile_flags: c_flags,
+            features: &features.enabled,
+        },
+        LanguageBuilder {
+            name: "C++",
+            extensions: &["cpp", "cc", "cxx", "c++"],
+            compiler: config.package.cpp_compiler.as_deref().unwrap_or("g++"),
+            include_flag: Some("-I"),
+            compile_flags: cpp_flags,
+            features: &features.enabled,
+        },
+    ]
+}
+
+/// C and C++ flags for a package: the root build's profile (optimisation,
+/// debug info, PIC, defines and any extra profile flags such as sanitizers)
+/// followed by the package's own `[options]` and its features' defines.
+fn package_flags(config: &io::Config, profile: &Profile, features: &Features) -> (String, String) {
+    let defines = features.define_flags();
+    let with_defines = |flags: &str| {
+        [flags, defines.as_str()]
+            .iter()
+            .filter(|f| !f.trim().is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    (
+        profile.compile_flags(&with_defines(&config.options.c_flags), false),
+        profile.compile_flags(
+            &with_defines(config.options.cpp_flags.as_deref().unwrap_or("")),
+            true,
+        ),
+    )
+}
+
+fn root_include_dirs() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("deps").join("headers"),
+        PathBuf::from("include"),
+    ]
+}
+
+fn dep_path(dep_node: &DepNode) -> PathBuf {
+    PathBuf::from("deps").join(&dep_node.dep.name)
+}
+
+/// Objects and archives of dependencies are per profile; only their sources
+/// and headers are shared in `deps/`.
+fn dep_out_dir(profile: &Profile, dep_node: &DepNode) -> PathBuf {
+    profile.target_dir().join("deps").join(&dep_node.dep.name)
+}
+
+fn dep_include_dirs(dep_node: &DepNode) -> Vec<PathBuf> {
+    let dep_path = dep_path(dep_node);
+    let mut include_dirs = vec![dep_path.join("include"), dep_path.join("external_headers")];
+    for dep in &dep_node.dependencies {
+        include_dirs.push(PathBuf::from("deps/headers").join(dep));
+    }
+    include_dirs
+}
+
+/// Include path for entry points outside `src/`; on top of the usual dirs,
+/// tests can `#include <seastar_test.h>`.
+fn entry_include_dirs(output_dir: &Path) -> Vec<PathBuf> {
+    let mut include_dirs = root_include_dirs();
+    include_dirs.push(output_dir.join("include"));
+    include_dirs
+}
+
+const TEST_HARNESS: &str = include_str!("seastar_test.h");
+
+fn write_test_harness(output_dir: &Path) {
+    let path = output_dir.join("include").join("seastar_test.h");
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == TEST_HARNESS) {
+        return;
+    }
+    std::fs::create_dir_all(output_dir.join("include"))
+        .expect("Failed to create include directory");
+    std::fs::write(&path, TEST_HARNESS).expect("Failed to write test harness header");
+}
+
+fn files_for(lang: &LanguageBuilder, all_files: &[PathBuf]) -> Vec<PathBuf> {
+    all_files
+        .iter()
+        .filter(|f| compilation::matches_lang(lang, f))
+        .cloned()
+        .collect()
+}
+
+/// What the dependencies contribute to the root package's links.
+#[derive(Default)]
+struct DepOutputs {
+    static_libs: Vec<PathBuf>,
+    /// The dependencies' own link flags (e.g. `-lpthread`), which their
+    /// archives need wherever they end up.
+    link_flags: Vec<String>,
+    /// Whether any dependency has C++ sources, so links need the C++ driver.
+    uses_cpp: bool,
+}
+
+impl DepOutputs {
+    fn extend(&mut self, other: &DepOutputs) {
+        self.static_libs.extend(other.static_libs.iter().cloned());
+        self.link_flags.extend(other.link_flags.iter().cloned());
+        self.uses_cpp |= other.uses_cpp;
+    }
+}
+
+/// Dependency outputs split by kind. Dev dependencies only go into tests
+/// and examples.
+#[derive(Default)]
+struct DepLinks {
+    normal: DepOutputs,
+    /// Normal and dev dependencies together, in build order.
+    with_dev: DepOutputs,
+}
+
+impl DepLinks {
+    fn for_target(&self, kind: TargetKind) -> &DepOutputs {
+        match kind {
+            TargetKind::Test | TargetKind::Example => &self.with_dev,
+            TargetKind::Lib | TargetKind::Bin => &self.normal,
+        }
+    }
+}
+
+/// Builds every dependency into a static archive, each with the compilers
+/// and `[options]` from its own Seastar.toml. Dev dependencies are only
+/// built when `with_dev` is set.
+fn build_deps(
+    graph: &DepGraph,
+    profile: &Profile,
+    jobs: usize,
+    with_dev: bool,
+    commands: &mut Vec<CompileCommand>,
+) -> DepLinks {
+    let mut links = DepLinks::default();
     for dep_node in graph.topological_order() {
+        if dep_node.kind == DepKind::Dev && !with_dev {
+            continue;
+        }
+        let mut outputs = DepOutputs::default();
         let dep_name = &dep_node.dep.name;
-        let dep_path = format!("deps/{}", dep_name);
+        let dep_path = dep_path(dep_node);
 
-        let src_headers = PathBuf::from(&dep_path).join("external_headers");
+        let src_headers = dep_path.join("external_headers");
         let dst_headers = PathBuf::from("deps").join("headers").join(dep_name);
         if src_headers.exists() {
-            println!(
+            eprintln!(
                 "Copying headers from {:?} to {:?}",
                 src_headers, dst_headers
             );
@@ -31,173 +195,479 @@ fn build_deps(deps: &[Dep]) -> Vec<PathBuf> {
                 .expect("Failed to copy headers");
         }
 
-        let dep_src = PathBuf::from(&dep_path).join("src");
-        let dep_obj_dir = PathBuf::from(&dep_path).join("obj");
-        let dep_lib = PathBuf::from(&dep_path).join(format!("lib{}.a", dep_name));
+        let dep_src = dep_path.join("src");
+        let dep_obj_dir = dep_out_dir(profile, dep_node).join("obj");
+        let dep_lib = dep_out_dir(profile, dep_node).join(format!("lib{}.a", dep_name));
 
-        let dep_src_files =
-            io::get_source_files(dep_src.to_str().unwrap(), &["c", "cpp", "cc", "cxx", "c++"]);
+        let dep_src_files = io::get_source_files(dep_src.to_str().unwrap(), SOURCE_EXTENSIONS);
 
         if !dep_src_files.is_empty() {
             let mut objects = Vec::new();
+            let include_dirs = dep_include_dirs(dep_node);
+            let dep_config = &dep_node.config;
+            let (c_flags, cpp_flags) = package_flags(dep_config, profile, &dep_node.features);
+            let uses_cpp = dep_src_files.iter().any(|f| is_cpp(f));
 
-            let mut include_dirs = vec![
-                PathBuf::from(&dep_path).join("include"),
-                PathBuf::from(&dep_path).join("external_headers"),
-            ];
-            
-            for dep in &dep_node.dependencies {
-                include_dirs.push(PathBuf::from("deps/headers").join(dep));
-            }
-
-            let c_files: Vec<_> = dep_src_files
-                .iter()
-                .filter(|f| {
-                    f.extension()
-                        .and_then(|e| e.to_str())
-                        .map(|ext| ext == "c")
-                        .unwrap_or(false)
-                })
-                .cloned()
-                .collect();
-
-            if !c_files.is_empty() {
+            for lang in &languages(dep_config, &c_flags, &cpp_flags, &dep_node.features) {
+                let src_files = files_for(lang, &dep_src_files);
+                if src_files.is_empty() {
+                    continue;
+                }
                 objects.extend(compilation::compile_files(
-                    &LanguageBuilder {
-                        name: "C",
-                        extensions: &["c"],
-                        compiler: "gcc",
-                        include_flag: Some("-I"),
-                        compile_flags: "",
-                    },
-                    &c_files,
+                    lang,
+                    &src_files,
+                    &dep_src,
                     &dep_obj_dir,
                     &include_dirs,
+                    jobs,
                 ));
-            }
-
-            let cpp_files: Vec<_> = dep_src_files
-                .iter()
-                .filter(|f| {
-                    f.extension()
-                        .and_then(|e| e.to_str())
-                        .map(|ext| ["cpp", "cc", "cxx", "c++"].contains(&ext))
-                        .unwrap_or(false)
-                })
-                .cloned()
-                .collect();
-
-            if !cpp_files.is_empty() {
-                objects.extend(compilation::compile_files(
-                    &LanguageBuilder {
-                        name: "C++",
-                        extensions: &["cpp", "cc", "cxx", "c++"],
-                        compiler: "g++",
-                        include_flag: Some("-I"),
-                        compile_flags: "",
-                    },
-                    &cpp_files,
+                commands.extend(compilation::compile_commands(
+                    lang,
+                    &src_files,
+                    &dep_src,
                     &dep_obj_dir,
                     &include_dirs,
                 ));
             }
 
-            linking::link_objects("ar", &objects, &dep_lib, &true, "");
+            compilation::prune_stale_objects(&dep_obj_dir, &objects);
+            linking::link_objects("ar", &objects, &dep_lib, &LinkKind::StaticLib, "");
+
+            let link_flags = if uses_cpp {
+                dep_config.options.cpp_link_flags.as_deref().unwrap_or("")
+            } else {
+                &dep_config.options.link_flags
+            };
+            if !link_flags.trim().is_empty() {
+                outputs.link_flags.push(link_flags.trim().to_string());
+            }
+            outputs.uses_cpp |= uses_cpp;
         }
 
         if dep_lib.exists() {
-            dep_static_libs.push(dep_lib);
+            outputs.static_libs.push(dep_lib);
+        }
+
+        if dep_node.kind == DepKind::Normal {
+            links.normal.extend(&outputs);
         }
+        links.with_dev.extend(&outputs);
     }
 
-    dep_static_libs
+    links
 }
 
-pub fn build(config: &io::Config, source_dir: &str, obj_dir: &str, output_dir: &str) -> String {
-    let deps = parse_deps(&config);
-    let dep_statics = build_deps(&deps);
-
-    let supported_extensions = &["c", "cpp", "cc", "cxx", "c++"];
-    let all_files = io::get_source_files(source_dir, supported_extensions);
+struct SharedLibNames {
+    file_name: String,
+    soname: String,
+    links: Vec<String>,
+}
 
-    let languages = [
-        LanguageBuilder {
-            name: "C",
-            extensions: &["c"],
-            compiler: &config.package.compiler,
-            include_flag: Some("-I"),
-            compile_flags: &config.options.c_flags,
-        },
-        LanguageBuilder {
-            name: "C++",
-            extensions: &["cpp", "cc", "cxx", "c++"],
-            compiler: config.package.cpp_compiler.as_deref().unwrap_or("g++"),
-            include_flag: Some("-I"),
-            compile_flags: config.options.cpp_flags.as_deref().unwrap_or(""),
+/// `foo` at version `1.2.3` is built as `libfoo.so.1.2.3`, with soname
+/// `libfoo.so.1` and a `libfoo.so -> libfoo.so.1` link for the linker.
+fn shared_lib_names(name: &str, version: Option<&semver::Version>) -> SharedLibNames {
+    let base = format!("lib{}.so", name);
+    match version {
+        Some(version) => {
+            let soname = format!("{}.{}", base, version.major);
+            SharedLibNames {
+                file_name: format!("{}.{}", base, version),
+                links: vec![base, soname.clone()],
+                soname,
+            }
+        }
+        None => SharedLibNames {
+            file_name: base.clone(),
+            soname: base,
+            links: Vec::new(),
         },
-    ];
+    }
+}
 
-    let obj_dir = PathBuf::from(obj_dir);
+/// Adjusts the selected profile for what the package builds; a shared
+/// library needs everything compiled as position-independent code.
+fn with_package_settings(config: &io::Config, profile: &Profile) -> Profile {
+    let builds_shared = config
+        .lib
+        .as_ref()
+        .is_some_and(|lib| lib.crate_type.is_shared());
+    Profile {
+        pic: profile.pic || builds_shared,
+        ..profile.clone()
+    }
+}
 
-    let mut all_objects = Vec::new();
+/// Something `build` produced.
+pub struct Artifact {
+    pub kind: TargetKind,
+    pub name: String,
+    pub path: PathBuf,
+    /// The `main` source file, for binaries, examples and tests.
+    pub entry: Option<PathBuf>,
+}
 
-    for lang in &languages {
-        let src_files: Vec<_> = all_files
-            .iter()
-            .filter(|f| {
-                f.extension()
-                    .and_then(|e| e.to_str())
-                    .map(|ext| lang.extensions.iter().any(|&x| ext.eq_ignore_ascii_case(x)))
-                    .unwrap_or(false)
-            })
-            .cloned()
-            .collect();
-        let include_dirs = vec![
-            PathBuf::from("deps").join("headers"),
-            PathBuf::from("include"),
-        ];
-
-        if !src_files.is_empty() {
-            let objects = compilation::compile_files(lang, &src_files, &obj_dir, &include_dirs);
-            all_objects.extend(objects);
+fn is_cpp(src_file: &Path) -> bool {
+    src_file
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| ["cpp", "cc", "cxx", "c++"].contains(&ext))
+        .unwrap_or(false)
+}
+
+/// Entry points of the targets being built that live outside the source
+/// directory (typically `examples/`). They're compiled on their own, since
+/// everything inside the source directory is compiled anyway.
+fn outside_entries(targets: &[Target], source_dir: &str) -> Vec<PathBuf> {
+    let source_dir = targets::normalize(Path::new(source_dir));
+    targets
+        .iter()
+        .filter_map(|t| t.entry.clone())
+        .filter(|entry| !entry.starts_with(&source_dir))
+        .collect()
+}
+
+/// Compiles the root package's sources and returns `(source, object)` pairs.
+/// With `jobs` set to `None` nothing is compiled; only `commands` is filled.
+fn compile_root(
+    languages: &[LanguageBuilder],
+    all_files: &[PathBuf],
+    src_dir: &Path,
+    obj_dir: &Path,
+    include_dirs: &[PathBuf],
+    jobs: Option<usize>,
+    commands: &mut Vec<CompileCommand>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut compiled = Vec::new();
+
+    for lang in languages {
+        let src_files = files_for(lang, all_files);
+        if src_files.is_empty() {
+            continue;
+        }
+        let lang_commands =
+            compilation::compile_commands(lang, &src_files, src_dir, obj_dir, include_dirs);
+        if let Some(jobs) = jobs {
+            let objects =
+                compilation::compile_files(lang, &src_files, src_dir, obj_dir, include_dirs, jobs);
+            compiled.extend(src_files.iter().map(|f| targets::normalize(f)).zip(objects));
+        }
+        commands.extend(lang_commands);
+    }
+
+    compiled
+}
+
+/// Writes `compile_commands.json` for the root package without compiling
+/// anything. With `include_deps`, dependencies are resolved (and fetched if
+/// needed) so their commands can be included too.
+pub fn write_compdb(
+    config: &io::Config,
+    profile: &Profile,
+    source_dir: &str,
+    include_deps: bool,
+    resolve: &ResolveOptions,
+) {
+    let profile = &with_package_settings(config, profile);
+    let mut commands = Vec::new();
+
+    let graph = include_deps.then(|| resolve_and_fetch(config, "deps/", resolve));
+    if let Some(graph) = &graph {
+        for dep_node in graph.topological_order() {
+            let dep_src = dep_path(dep_node).join("src");
+            let dep_src_files = io::get_source_files(dep_src.to_str().unwrap(), SOURCE_EXTENSIONS);
+            let features = &dep_node.features;
+            let (c_flags, cpp_flags) = package_flags(&dep_node.config, profile, features);
+            for lang in &languages(&dep_node.config, &c_flags, &cpp_flags, features) {
+                commands.extend(compilation::compile_commands(
+                    lang,
+                    &files_for(lang, &dep_src_files),
+                    &dep_src,
+                    &dep_out_dir(profile, dep_node).join("obj"),
+                    &dep_include_dirs(dep_node),
+                ));
+            }
         }
     }
 
-    let is_any_cpp = all_files.iter().any(|f| {
-        f.extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| ["cpp", "cc", "cxx", "c++"].contains(&ext))
-            .unwrap_or(false)
-    });
-    let linker = if is_any_cpp {
-        config.package.cpp_compiler.as_deref().unwrap_or("g++")
-    } else {
-        &config.package.compiler
+    let features = match &graph {
+        Some(graph) => graph.root_features.clone(),
+        None => root_features(config, resolve),
     };
-    let link_flags = if is_any_cpp {
-        config.options.cpp_link_flags.as_deref().unwrap_or("")
-    } else {
-        &config.options.link_flags
+    let (c_flags, cpp_flags) = package_flags(config, profile, &features);
+    let languages = languages(config, &c_flags, &cpp_flags, &features);
+    let output_dir = profile.target_dir();
+
+    let all_targets = targets::resolve(config);
+    if all_targets.iter().any(|t| t.kind == TargetKind::Test) {
+        write_test_harness(&output_dir);
+    }
+
+    let all_files = io::get_source_files(source_dir, SOURCE_EXTENSIONS);
+    compile_root(
+        &languages,
+        &all_files,
+        Path::new(source_dir),
+        &output_dir.join("obj"),
+        &root_include_dirs(),
+        None,
+        &mut commands,
+    );
+    compile_root(
+        &languages,
+        &outside_entries(&all_targets, source_dir),
+        Path::new("."),
+        &output_dir.join("entry_obj"),
+        &entry_include_dirs(&output_dir),
+        None,
+        &mut commands,
+    );
+
+    compdb::write(Path::new(compdb::COMPDB_PATH), &commands);
+}
+
+/// Builds the library and binaries of the package, plus any examples or
+/// tests whose kind is listed in `extra`.
+pub fn build(
+    config: &io::Config,
+    profile: &Profile,
+    source_dir: &str,
+    jobs: usize,
+    resolve: &ResolveOptions,
+    extra: &[TargetKind],
+) -> Vec<Artifact> {
+    let mut commands = Vec::new();
+
+    let profile = &with_package_settings(config, profile);
+
+    let all_targets = targets::resolve(config);
+    let wanted: Vec<Target> = all_targets
+        .iter()
+        .filter(|t| matches!(t.kind, TargetKind::Lib | TargetKind::Bin) || extra.contains(&t.kind))
+        .cloned()
+        .collect();
+
+    let with_dev = wanted
+        .iter()
+        .any(|t| matches!(t.kind, TargetKind::Test | TargetKind::Example));
+    let graph = resolve_and_fetch(config, "deps/", resolve);
+    let dep_links = build_deps(&graph, profile, jobs, with_dev, &mut commands);
+
+    let all_files = io::get_source_files(source_dir, SOURCE_EXTENSIONS);
+
+    let output_dir = profile.target_dir();
+    let obj_dir = output_dir.join("obj");
+
+    let (c_flags, cpp_flags) = package_flags(config, profile, &graph.root_features);
+    let languages = languages(config, &c_flags, &cpp_flags, &graph.root_features);
+
+    let mut compiled = compile_root(
+        &languages,
+        &all_files,
+        Path::new(source_dir),
+        &obj_dir,
+        &root_include_dirs(),
+        Some(jobs),
+        &mut commands,
+    );
+    let src_objects: Vec<PathBuf> = compiled.iter().map(|(_, obj)| obj.clone()).collect();
+    compilation::prune_stale_objects(&obj_dir, &src_objects);
+
+    if wanted.iter().any(|t| t.kind == TargetKind::Test) {
+        write_test_harness(&output_dir);
+    }
+    let entry_obj_dir = output_dir.join("entry_obj");
+    compiled.extend(compile_root(
+        &languages,
+        &outside_entries(&wanted, source_dir),
+        Path::new("."),
+        &entry_obj_dir,
+        &entry_include_dirs(&output_dir),
+        Some(jobs),
+        &mut commands,
+    ));
+    // Targets that weren't asked for this time keep their objects; only
+    // those of removed or renamed entry points go.
+    let entry_objects: Vec<PathBuf> = outside_entries(&all_targets, source_dir)
+        .iter()
+        .map(|entry| compilation::object_path(entry, Path::new("."), &entry_obj_dir))
+        .collect();
+    compilation::prune_stale_objects(&entry_obj_dir, &entry_objects);
+    compdb::write(Path::new(compdb::COMPDB_PATH), &commands);
+
+    let object_for = |src: &Path| {
+        compiled
+            .iter()
+            .find(|(s, _)| s == src)
+            .map(|(_, obj)| obj.clone())
     };
 
-    let output_path = if config.package.is_lib {
-        PathBuf::from(output_dir)
-            .join(&config.package.project_name)
-            .with_extension("a")
-    } else {
-        PathBuf::from(output_dir).join(&config.package.project_name)
+    // Everything in `src/` that isn't some target's `main` is shared: it
+    // forms the library, or is linked into every binary if there's none.
+    let entries: Vec<&PathBuf> = all_targets
+        .iter()
+        .filter_map(|t| t.entry.as_ref())
+        .collect();
+    let shared_sources: Vec<PathBuf> = all_files
+        .iter()
+        .map(|f| targets::normalize(f))
+        .filter(|f| !entries.contains(&f))
+        .collect();
+    let shared_objects: Vec<PathBuf> = shared_sources
+        .iter()
+        .filter_map(|f| object_for(f))
+        .collect();
+    let shared_is_cpp = shared_sources.iter().any(|f| is_cpp(f));
+
+    // Without a library, examples and tests link the shared sources directly,
+    // and the implicit binary's `main.c` has to stay out of those.
+    let has_implicit_bin = all_targets
+        .iter()
+        .any(|t| t.kind == TargetKind::Bin && t.entry.is_none());
+    let support_objects: Vec<PathBuf> = shared_sources
+        .iter()
+        .filter(|f| !(has_implicit_bin && f.file_stem().is_some_and(|stem| stem == "main")))
+        .filter_map(|f| object_for(f))
+        .collect();
+
+    // A C++ dependency needs the C++ driver for its runtime, even when
+    // linked into a C program.
+    let linker_for = |uses_cpp: bool, deps: &DepOutputs| {
+        let (linker, package_flags) = if uses_cpp || deps.uses_cpp {
+            (
+                config.package.cpp_compiler.as_deref().unwrap_or("g++"),
+                config.options.cpp_link_flags.as_deref().unwrap_or(""),
+            )
+        } else {
+            (
+                config.package.compiler.as_str(),
+                config.options.link_flags.as_str(),
+            )
+        };
+        let mut link_flags = profile.link_flags(package_flags);
+        if !deps.link_flags.is_empty() {
+            link_flags = format!("{} {}", link_flags, deps.link_flags.join(" "));
+        }
+        (linker, link_flags)
     };
 
-    all_objects.extend_from_slice(&dep_statics);
-    linking::link_objects(
-        linker,
-        &all_objects,
-        &output_path,
-        &config.package.is_lib,
-        link_flags,
-    );
+    let mut artifacts = Vec::new();
+    let mut lib_archive = None;
+    let mut lib_shared = None;
+    let crate_type = config
+        .lib
+        .as_ref()
+        .map(|lib| lib.crate_type)
+        .unwrap_or_default();
+
+    for target in wanted.iter().filter(|t| t.kind == TargetKind::Lib) {
+        if crate_type.is_static() {
+            let output_path = output_dir.join(format!("lib{}.a", target.name));
+            linking::link_objects(
+                "ar",
+                &shared_objects,
+                &output_path,
+                &LinkKind::StaticLib,
+                "",
+            );
+            lib_archive = Some(output_path.clone());
+            artifacts.push(Artifact {
+                kind: target.kind,
+                name: target.name.clone(),
+                path: output_path,
+                entry: None,
+            });
+        }
+
+        if crate_type.is_shared() {
+            let names = shared_lib_names(&target.name, config.package.version.as_ref());
+            let output_path = output_dir.join(&names.file_name);
+            let mut inputs = shared_objects.clone();
+            inputs.extend_from_slice(&dep_links.normal.static_libs);
+
+            let (linker, link_flags) = linker_for(shared_is_cpp, &dep_links.normal);
+            linking::link_objects(
+                linker,
+                &inputs,
+                &output_path,
+                &LinkKind::SharedLib {
+                    soname: names.soname,
+                },
+                &link_flags,
+            );
+            linking::create_soname_links(&output_path, &names.links);
+            lib_shared = Some(output_path.clone());
+            artifacts.push(Artifact {
+                kind: target.kind,
+                name: target.name.clone(),
+                path: output_path,
+                entry: None,
+            });
+        }
+    }
+
+    for target in wanted.iter().filter(|t| t.kind != TargetKind::Lib) {
+        let mut rpath = None;
+        let (mut inputs, uses_cpp) = match &target.entry {
+            None => (src_objects.clone(), all_files.iter().any(|f| is_cpp(f))),
+            Some(entry) => {
+                let Some(entry_object) = object_for(entry) else {
+                    eprintln!(
+                        "Error: {} '{}' has no compiled entry point {}",
+                        target.kind.describe(),
+                        target.name,
+                        entry.display()
+                    );
+                    exit(1);
+                };
+                let mut inputs = vec![entry_object];
+                // Prefer the archive when there is one, so binaries stay
+                // self-contained; otherwise find the .so next to the binary.
+                match (&lib_archive, &lib_shared) {
+                    (Some(archive), _) => inputs.push(archive.clone()),
+                    (None, Some(shared)) => {
+                        inputs.push(shared.clone());
+                        rpath = Some(match target.kind {
+                            TargetKind::Example | TargetKind::Test => "-Wl,-rpath,$ORIGIN/..",
+                            _ => "-Wl,-rpath,$ORIGIN",
+                        });
+                    }
+                    (None, None) => inputs.extend(support_objects.iter().cloned()),
+                }
+                (inputs, is_cpp(entry) || shared_is_cpp)
+            }
+        };
+        let deps = dep_links.for_target(target.kind);
+        inputs.extend_from_slice(&deps.static_libs);
+
+        let output_path = match target.kind {
+            TargetKind::Example => output_dir.join("examples").join(&target.name),
+            TargetKind::Test => output_dir.join("tests").join(&target.name),
+            _ => output_dir.join(&target.name),
+        };
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent).expect("Failed to create output directory");
+        }
+
+        let (linker, mut link_flags) = linker_for(uses_cpp, deps);
+        if let Some(rpath) = rpath {
+            link_flags = format!("{} {}", link_flags, rpath);
+        }
+        linking::link_objects(
+            linker,
+            &inputs,
+            &output_path,
+            &LinkKind::Executable,
+            &link_flags,
+        );
+        artifacts.push(Artifact {
+            kind: target.kind,
+            name: target.name.clone(),
+            path: output_path,
+            entry: target.entry.clone(),
+        });
+    }
 
-    println!();
+    eprintln!();
 
-    output_path.display().to_string()
+    artifacts
 }
@@ -0,0 +1,122 @@
+/*
+ * seastar_test.h - the test harness bundled with Seastar.
+ *
+ * Include this from a file in `tests/` and write tests with TEST(name).
+ * Seastar supplies `main`, so a test file needs nothing else:
+ *
+ *     #include <seastar_test.h>
+ *     #include "mylib.h"
+ *
+ *     TEST(adds_numbers) {
+ *         ASSERT_EQ(add(2, 2), 4);
+ *     }
+ *
+ * ASSERT* macros stop the current test when they fail, EXPECT* macros
+ * record the failure and carry on. Define SEASTAR_TEST_NO_MAIN before
+ * including this header to provide your own `main`.
+ *
+ * The generated `main` understands:
+ *     --list    print the name of every test, one per line
+ *     <name>    run only the test called <name>
+ * and runs every test when given no arguments.
+ */
+
+#ifndef SEASTAR_TEST_H
+#define SEASTAR_TEST_H
+
+#include <stdio.h>
+#include <string.h>
+
+#ifndef SEASTAR_TEST_MAX
+#define SEASTAR_TEST_MAX 1024
+#endif
+
+typedef void (*seastar_test_fn)(void);
+
+struct seastar_test_case {
+    const char *name;
+    seastar_test_fn fn;
+};
+
+static struct seastar_test_case seastar_tests[SEASTAR_TEST_MAX];
+static int seastar_test_count = 0;
+static int seastar_test_failures = 0;
+
+__attribute__((unused)) static void seastar_test_register(const char *name, seastar_test_fn fn)
+{
+    if (seastar_test_count >= SEASTAR_TEST_MAX) {
+        fprintf(stderr, "seastar_test.h: too many tests, raise SEASTAR_TEST_MAX\n");
+        return;
+    }
+    seastar_tests[seastar_test_count].name = name;
+    seastar_tests[seastar_test_count].fn = fn;
+    seastar_test_count++;
+}
+
+#define TEST(name)                                                             \
+    static void seastar_test_##name(void);                                     \
+    __attribute__((constructor)) static void seastar_test_register_##name(void) \
+    {                                                                          \
+        seastar_test_register(#name, seastar_test_##name);                     \
+    }                                                                          \
+    static void seastar_test_##name(void)
+
+#define SEASTAR_TEST_CHECK(cond, text, on_fail)                                \
+    do {                                                                       \
+        if (!(cond)) {                                                         \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,   \
+                    text);                                                     \
+            seastar_test_failures++;                                           \
+            on_fail;                                                           \
+        }                                                                      \
+    } while (0)
+
+#define ASSERT(cond) SEASTAR_TEST_CHECK((cond), #cond, return)
+#define ASSERT_EQ(a, b) SEASTAR_TEST_CHECK((a) == (b), #a " == " #b, return)
+#define ASSERT_NE(a, b) SEASTAR_TEST_CHECK((a) != (b), #a " != " #b, return)
+#define ASSERT_STREQ(a, b)                                                     \
+    SEASTAR_TEST_CHECK(strcmp((a), (b)) == 0, #a " == " #b, return)
+
+#define EXPECT(cond) SEASTAR_TEST_CHECK((cond), #cond, (void)0)
+#define EXPECT_EQ(a, b) SEASTAR_TEST_CHECK((a) == (b), #a " == " #b, (void)0)
+#define EXPECT_NE(a, b) SEASTAR_TEST_CHECK((a) != (b), #a " != " #b, (void)0)
+#define EXPECT_STREQ(a, b)                                                     \
+    SEASTAR_TEST_CHECK(strcmp((a), (b)) == 0, #a " == " #b, (void)0)
+
+#ifndef SEASTAR_TEST_NO_MAIN
+int main(int argc, char **argv)
+{
+    const char *only = argc > 1 ? argv[1] : NULL;
+    int ran = 0;
+    int failed = 0;
+    int i;
+
+    if (only && strcmp(only, "--list") == 0) {
+        for (i = 0; i < seastar_test_count; i++) {
+            puts(seastar_tests[i].name);
+        }
+        return 0;
+    }
+
+    for (i = 0; i < seastar_test_count; i++) {
+        int before = seastar_test_failures;
+        if (only && strcmp(only, seastar_tests[i].name) != 0) {
+            continue;
+        }
+        ran++;
+        seastar_tests[i].fn();
+        if (seastar_test_failures != before) {
+            fprintf(stderr, "test %s failed\n", seastar_tests[i].name);
+            failed++;
+        }
+    }
+
+    if (only && ran == 0) {
+        fprintf(stderr, "no test named '%s'\n", only);
+        return 2;
+    }
+    return failed ? 1 : 0;
+}
+#endif
+
+#endif /* SEASTAR_TEST_H */
@@ -0,0 +1,149 @@
+// Works out which artifacts a package produces. A package can declare a
+// `[lib]`, any number of `[[bin]]` and `[[example]]` tables; packages that
+// declare none of them keep the old behaviour of one binary (or, with
+// `is_lib`, one static library) built from everything in `src/`.
+
+use std::path::{Component, Path, PathBuf};
+use std::process::exit;
+
+use crate::io::Config;
+
+pub const TESTS_DIR: &str = "tests";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    /// A program built from one file in `tests/`, run by `seastar test`.
+    Test,
+}
+
+impl TargetKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TargetKind::Lib => "library",
+            TargetKind::Bin => "binary",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub kind: TargetKind,
+    pub name: String,
+    /// The source file with `main` for binaries and examples. `None` for
+    /// the library and for the implicit binary, which uses all of `src/`.
+    pub entry: Option<PathBuf>,
+}
+
+/// `./src/main.c` and `src/main.c` should compare equal.
+pub fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+pub fn resolve(config: &Config) -> Vec<Target> {
+    let mut targets = Vec::new();
+
+    if config.lib.is_some() || config.package.is_lib {
+        let name = config
+            .lib
+            .as_ref()
+            .and_then(|lib| lib.name.clone())
+            .unwrap_or_else(|| config.package.project_name.clone());
+        targets.push(Target {
+            kind: TargetKind::Lib,
+            name,
+            entry: None,
+        });
+    }
+
+    for (kind, tables) in [
+        (TargetKind::Bin, &config.bin),
+        (TargetKind::Example, &config.example),
+    ] {
+        for table in tables {
+            let entry = normalize(Path::new(&table.path));
+            if !entry.is_file() {
+                eprintln!(
+                    "Error: {} '{}' points at {}, which does not exist",
+                    kind.describe(),
+                    table.name,
+                    entry.display()
+                );
+                exit(1);
+            }
+            let is_source = entry
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| super::SOURCE_EXTENSIONS.contains(&ext));
+            if !is_source {
+                eprintln!(
+                    "Error: {} '{}' points at {}, which is not a C or C++ source file ({})",
+                    kind.describe(),
+                    table.name,
+                    entry.display(),
+                    super::SOURCE_EXTENSIONS.join(", ")
+                );
+                exit(1);
+            }
+            if targets
+                .iter()
+                .any(|t: &Target| t.kind == kind && t.name == table.name)
+            {
+                eprintln!(
+                    "Error: {} '{}' is declared more than once",
+                    kind.describe(),
+                    table.name
+                );
+                exit(1);
+            }
+            targets.push(Target {
+                kind,
+                name: table.name.clone(),
+                entry: Some(entry),
+            });
+        }
+    }
+
+    if targets.is_empty() {
+        targets.push(Target {
+            kind: TargetKind::Bin,
+            name: config.package.project_name.clone(),
+            entry: None,
+        });
+    }
+
+    targets.extend(discover_tests());
+    targets
+}
+
+/// Every source file under `tests/` is its own test program, named after
+/// its path: `tests/net/parse.c` becomes `net-parse`.
+fn discover_tests() -> Vec<Target> {
+    let mut tests: Vec<Target> = crate::io::get_source_files(TESTS_DIR, super::SOURCE_EXTENSIONS)
+        .into_iter()
+        .map(|path| {
+            let rel = path
+                .strip_prefix(TESTS_DIR)
+                .unwrap_or(&path)
+                .with_extension("");
+            let name = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("-");
+            Target {
+                kind: TargetKind::Test,
+                name,
+                entry: Some(normalize(&path)),
+            }
+        })
+        .collect();
+    tests.sort_by(|a, b| a.name.cmp(&b.name));
+    tests
+}
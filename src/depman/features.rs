@@ -0,0 +1,186 @@
+// `[features]` in Seastar.toml. Each feature lists what it turns on:
+// `define:NAME` (or `define:NAME=VALUE`) for a `-D` define, `dep:name` for an
+// optional dependency, or the name of another feature. `default` is on
+// unless turned off, and an optional dependency's name works as a feature
+// of its own when the table doesn't say otherwise.
+
+use std::collections::BTreeSet;
+
+use crate::io::Config;
+
+pub const DEFAULT: &str = "default";
+
+/// What a set of requested features turns on in one package.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    /// Every enabled feature, including the ones others pulled in.
+    pub enabled: BTreeSet<String>,
+    /// Defines to pass with `-D`.
+    pub defines: BTreeSet<String>,
+    /// Optional dependencies to use.
+    pub deps: BTreeSet<String>,
+}
+
+impl Features {
+    pub fn define_flags(&self) -> String {
+        self.defines
+            .iter()
+            .map(|define| format!("-D{}", define))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn is_optional_dep(config: &Config, name: &str) -> bool {
+    config
+        .dependencies
+        .get(name)
+        .or_else(|| config.dev_dependencies.get(name))
+        .is_some_and(|spec| spec.is_optional())
+}
+
+/// Works out what `requested` turns on in `config`'s package. `default` is
+/// skipped quietly when the package doesn't define it.
+pub fn resolve(config: &Config, requested: &BTreeSet<String>) -> Result<Features, String> {
+    let package = &config.package.project_name;
+    let mut features = Features::default();
+    let mut pending: Vec<String> = requested.iter().rev().cloned().collect();
+
+    while let Some(name) = pending.pop() {
+        if features.enabled.contains(&name) {
+            continue;
+        }
+        let Some(entries) = config.features.get(&name) else {
+            if is_optional_dep(config, &name) {
+                features.deps.insert(name.clone());
+                features.enabled.insert(name);
+            } else if name != DEFAULT {
+                return Err(format!("package '{}' has no feature '{}'", package, name));
+            }
+            continue;
+        };
+        features.enabled.insert(name.clone());
+
+        for entry in entries {
+            if let Some(define) = entry.strip_prefix("define:") {
+                if define.is_empty() {
+                    return Err(format!(
+                        "feature '{}' of '{}' has an empty `define:` entry",
+                        name, package
+                    ));
+                }
+                features.defines.insert(define.to_string());
+            } else if let Some(dep) = entry.strip_prefix("dep:") {
+                if !is_optional_dep(config, dep) {
+                    return Err(format!(
+                        "feature '{}' of '{}' enables '{}', which is not an optional dependency",
+                        name, package, dep
+                    ));
+                }
+                features.deps.insert(dep.to_string());
+            } else {
+                pending.push(entry.clone());
+            }
+        }
+    }
+
+    Ok(features)
+}
+
+/// The features a dependent asks for: the listed ones, plus `default`
+/// unless it turned that off.
+pub fn requested(features: &[String], default_features: bool) -> BTreeSet<String> {
+    let mut requested: BTreeSet<String> = features.iter().cloned().collect();
+    if default_features {
+        requested.insert(DEFAULT.to_string());
+    }
+    requested
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!(
+            "[package]\nproject_name = \"pkg\"\ncompiler = \"cc\"\n\n[options]\nc_flags = \"\"\nlink_flags = \"\"\n\n{}",
+            extra
+        ))
+        .unwrap()
+    }
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn default_features() {
+        let config = config("[features]\ndefault = [\"tls\"]\ntls = [\"define:WITH_TLS\"]\n");
+        let on = resolve(&config, &requested(&[], true)).unwrap();
+        assert_eq!(on.enabled, set(&["default", "tls"]));
+        assert_eq!(on.define_flags(), "-DWITH_TLS");
+
+        let off = resolve(&config, &requested(&[], false)).unwrap();
+        assert_eq!(off, Features::default());
+    }
+
+    #[test]
+    fn missing_default_is_skipped() {
+        let features = resolve(&config(""), &requested(&[], true)).unwrap();
+        assert_eq!(features, Features::default());
+    }
+
+    #[test]
+    fn dep_activation() {
+        let config = config(
+            "[dependencies]\nz = { path = \"../z\", optional = true }\nlog = { path = \"../log\", optional = true }\n\n[features]\ncompress = [\"dep:z\"]\n",
+        );
+        let features = resolve(&config, &set(&["compress"])).unwrap();
+        assert_eq!(features.enabled, set(&["compress"]));
+        assert_eq!(features.deps, set(&["z"]));
+
+        // An optional dependency's name is a feature of its own.
+        let features = resolve(&config, &set(&["log"])).unwrap();
+        assert_eq!(features.enabled, set(&["log"]));
+        assert_eq!(features.deps, set(&["log"]));
+    }
+
+    #[test]
+    fn transitive_features() {
+        let config = config(
+            "[dependencies]\nz = { path = \"../z\", optional = true }\n\n[features]\nfull = [\"tls\", \"compress\"]\ntls = [\"define:WITH_TLS\", \"compress\"]\ncompress = [\"define:LEVEL=2\", \"dep:z\"]\n",
+        );
+        let features = resolve(&config, &set(&["full"])).unwrap();
+        assert_eq!(features.enabled, set(&["compress", "full", "tls"]));
+        assert_eq!(features.defines, set(&["LEVEL=2", "WITH_TLS"]));
+        assert_eq!(features.deps, set(&["z"]));
+    }
+
+    #[test]
+    fn unknown_feature() {
+        let err = resolve(&config(""), &set(&["nope"])).unwrap_err();
+        assert_eq!(err, "package 'pkg' has no feature 'nope'");
+
+        let config = config("[features]\nbad = [\"missing\"]\n");
+        let err = resolve(&config, &set(&["bad"])).unwrap_err();
+        assert_eq!(err, "package 'pkg' has no feature 'missing'");
+    }
+
+    #[test]
+    fn dep_that_is_not_optional() {
+        let config =
+            config("[dependencies]\nz = { path = \"../z\" }\n\n[features]\nc = [\"dep:z\"]\n");
+        let err = resolve(&config, &set(&["c"])).unwrap_err();
+        assert_eq!(
+            err,
+            "feature 'c' of 'pkg' enables 'z', which is not an optional dependency"
+        );
+    }
+
+    #[test]
+    fn empty_define() {
+        let config = config("[features]\nc = [\"define:\"]\n");
+        let err = resolve(&config, &set(&["c"])).unwrap_err();
+        assert_eq!(err, "feature 'c' of 'pkg' has an empty `define:` entry");
+    }
+}
@@ -10,13 +10,16 @@ pub fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
         let entry = entry?;
         let file_type = entry.file_type()?;
         let src_path = entry.path();
+        // Git metadata from cached clones is never part of a package.
+        if entry.file_name() == ".git" {
+            continue;
+        }
         let dst_path = dst.join(entry.file_name());
         if file_type.is_dir() {
             copy_dir_recursive(&src_path, &dst_path)?;
         } else if file_type.is_file() {
             fs::copy(&src_path, &dst_path)?;
-        }
-        else {
+        } else {
             eprintln!("Warning: other file types not supported yet!");
         }
     }
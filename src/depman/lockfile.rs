@@ -0,0 +1,188 @@
+// Seastar.lock pins every package in the resolved dependency graph to an
+// exact commit and content checksum, so everyone building from the same
+// Seastar.toml gets the same code. Only `seastar update` moves the pins.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+pub const LOCKFILE_PATH: &str = "Seastar.lock";
+const LOCKFILE_VERSION: u32 = 1;
+/// Marks a git source resolved against its version tags.
+pub const VERSIONS_QUERY: &str = "versions";
+const HEADER: &str = "# This file is generated by Seastar and pins the exact dependency versions.\n# Do not edit it by hand; run `seastar update` to move the pins.\n\n";
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct Lockfile {
+    pub version: u32,
+
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LockedPackage {
+    pub name: String,
+    pub source: String,
+    /// The version a `version = "..."` requirement was resolved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub checksum: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+impl Lockfile {
+    pub fn new(mut packages: Vec<LockedPackage>) -> Lockfile {
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        for package in &mut packages {
+            package.dependencies.sort();
+        }
+        Lockfile {
+            version: LOCKFILE_VERSION,
+            packages,
+        }
+    }
+
+    pub fn find(&self, name: &str, source: &str) -> Option<&LockedPackage> {
+        self.packages
+            .iter()
+            .find(|p| p.name == name && p.source == source)
+    }
+}
+
+/// How a package's pin differs between two lockfiles.
+pub enum Change<'a> {
+    Added(&'a LockedPackage),
+    Removed(&'a LockedPackage),
+    Updated {
+        old: &'a LockedPackage,
+        new: &'a LockedPackage,
+    },
+}
+
+impl Lockfile {
+    /// Every package whose source, commit or checksum differs from `old`,
+    /// in name order.
+    pub fn changes_since<'a>(&'a self, old: &'a Lockfile) -> Vec<Change<'a>> {
+        let mut changes: Vec<Change> = old
+            .packages
+            .iter()
+            .filter(|p| !self.packages.iter().any(|n| n.name == p.name))
+            .map(Change::Removed)
+            .collect();
+
+        for new in &self.packages {
+            match old.packages.iter().find(|p| p.name == new.name) {
+                None => changes.push(Change::Added(new)),
+                Some(old)
+                    if old.source != new.source
+                        || old.commit != new.commit
+                        || old.checksum != new.checksum =>
+                {
+                    changes.push(Change::Updated { old, new })
+                }
+                Some(_) => {}
+            }
+        }
+
+        changes.sort_by(|a, b| a.name().cmp(b.name()));
+        changes
+    }
+}
+
+impl Change<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Change::Added(p) | Change::Removed(p) => &p.name,
+            Change::Updated { new, .. } => &new.name,
+        }
+    }
+}
+
+impl LockedPackage {
+    /// A short description of what the package is pinned to: the version
+    /// and abbreviated commit for git packages, the start of the checksum
+    /// otherwise.
+    pub fn short_pin(&self) -> String {
+        let pin = self.commit.as_deref().unwrap_or(&self.checksum);
+        let pin = &pin[..pin.len().min(10)];
+        match &self.version {
+            Some(version) => format!("v{} ({})", version, pin),
+            None => pin.to_string(),
+        }
+    }
+}
+
+/// A missing lockfile is an empty one.
+pub fn load(path: &Path) -> Lockfile {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(_) => return Lockfile::default(),
+    };
+    match toml::from_str::<Lockfile>(&data) {
+        Ok(mut lock) if lock.version <= LOCKFILE_VERSION => {
+            for package in &mut lock.packages {
+                package.source = migrate_source(&package.source);
+            }
+            lock
+        }
+        Ok(lock) => {
+            eprintln!(
+                "Error: {} has version {}, but this Seastar only understands up to {}",
+                path.display(),
+                lock.version,
+                LOCKFILE_VERSION
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: failed to parse {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Older lockfiles and vendor configs kept the version requirement of
+/// whichever dependent came first in versioned sources; drop it.
+pub fn migrate_source(source: &str) -> String {
+    match source.split_once("?version=") {
+        Some((base, _)) if base.starts_with("git+") => format!("{}?{}", base, VERSIONS_QUERY),
+        Some((base, _)) => base.to_string(),
+        None => source.to_string(),
+    }
+}
+
+pub fn save(path: &Path, lock: &Lockfile) {
+    let body = toml::to_string(lock).expect("Failed to serialize lockfile");
+    if let Err(e) = fs::write(path, format!("{}{}", HEADER, body)) {
+        eprintln!("Error: failed to write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+/// SHA-256 over the relative path and contents of every file in `dir`
+/// (ignoring `.git`), in a stable order.
+pub fn checksum_dir(dir: &Path) -> String {
+    let mut files: Vec<_> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git")
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let rel = file.strip_prefix(dir).unwrap_or(&file);
+        hasher.update(rel.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(&file).unwrap_or_default());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
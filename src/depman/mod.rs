@@ -1,5 +1,11 @@
 // This is the primary source for Seastar's C and C++ dependency manager
 // It is still extremely basic, but is going to be developed.
 
+pub mod features;
+pub mod fs_copy;
+pub mod lockfile;
+pub mod package;
+pub mod registry;
 pub mod resolver;
-pub mod fs_copy;
\ No newline at end of file
+pub mod tree;
+pub mod vendor;
@@ -0,0 +1,191 @@
+// `seastar package` turns a library into a `.tar.gz` a registry can hold.
+// Archives are reproducible: the same sources always give the same bytes,
+// so the checksum next to the archive identifies the release.
+
+use flate2::Compression;
+use flate2::GzBuilder;
+use flate2::read::GzDecoder;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process::exit;
+
+use super::lockfile;
+use crate::io::{Config, DepSpec};
+
+pub const PACKAGE_DIR: &str = "target/package";
+
+/// What goes into a package, relative to the project root.
+const PACKAGE_DIRS: &[&str] = &["src", "include", "external_headers"];
+const MANIFEST: &str = "Seastar.toml";
+
+pub struct Packaged {
+    pub name: String,
+    pub version: Version,
+    pub description: Option<String>,
+    pub archive: PathBuf,
+    /// SHA-256 of the archive itself.
+    pub archive_checksum: String,
+    /// Checksum of the unpacked contents, as recorded in Seastar.lock.
+    pub checksum: String,
+}
+
+/// Checks that the manifest describes something that can be published.
+fn validate(config: &Config) -> Result<Version, String> {
+    let package = &config.package;
+    if package.project_name.is_empty()
+        || !package
+            .project_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "package name '{}' may only contain letters, digits, '-' and '_'",
+            package.project_name
+        ));
+    }
+    let Some(version) = &package.version else {
+        return Err("`version` must be set in [package] to make a package".to_string());
+    };
+    if !Path::new("src").is_dir() {
+        return Err("there is no src/ directory".to_string());
+    }
+    let target_deps = config.target.values().flat_map(|t| &t.dependencies);
+    for (name, spec) in config.dependencies.iter().chain(target_deps) {
+        if let DepSpec::Detailed { path: Some(_), .. } = spec {
+            return Err(format!(
+                "dependency '{}' is a path dependency, which can't be resolved once published",
+                name
+            ));
+        }
+    }
+    Ok(version.clone())
+}
+
+/// Every file that goes into the package, relative and sorted.
+fn package_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = PACKAGE_DIRS
+        .iter()
+        .flat_map(|dir| {
+            walkdir::WalkDir::new(dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .map(|e| e.into_path())
+        })
+        .collect();
+    files.push(PathBuf::from(MANIFEST));
+    files.sort();
+    files
+}
+
+/// Writes the files under `prefix/` in the archive with fixed metadata, so
+/// timestamps, owners and umask don't leak into the result.
+fn write_archive(archive: &Path, prefix: &str, files: &[PathBuf]) -> io::Result<()> {
+    let gz = GzBuilder::new()
+        .mtime(0)
+        .write(File::create(archive)?, Compression::best());
+    let mut tar = tar::Builder::new(gz);
+    tar.mode(tar::HeaderMode::Deterministic);
+
+    for file in files {
+        let data = fs::read(file)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        tar.append_data(&mut header, Path::new(prefix).join(file), data.as_slice())?;
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Validates the manifest and writes `target/package/<name>-<version>.tar.gz`
+/// plus a `.sha256` file next to it.
+pub fn package(config: &Config) -> Packaged {
+    let version = validate(config).unwrap_or_else(|e| {
+        eprintln!("Error: can't package: {}", e);
+        exit(1);
+    });
+    let name = config.package.project_name.clone();
+    let base = format!("{}-{}", name, version);
+    let files = package_files();
+
+    // Stage a copy so the content checksum is computed exactly the way it
+    // will be once the archive is unpacked in someone's deps/.
+    let staging = Path::new(PACKAGE_DIR).join(&base);
+    if staging.exists() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    for file in &files {
+        let dst = staging.join(file);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).expect("Failed to create package directory");
+        }
+        fs::copy(file, &dst).expect("Failed to stage package file");
+    }
+    let checksum = lockfile::checksum_dir(&staging);
+
+    let archive = Path::new(PACKAGE_DIR).join(format!("{}.tar.gz", base));
+    let archive_checksum = write_archive(&archive, &base, &files)
+        .and_then(|_| sha256_file(&archive))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: failed to write {}: {}", archive.display(), e);
+            exit(1);
+        });
+
+    let file_name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let sum_path = archive.with_extension("gz.sha256");
+    if let Err(e) = fs::write(&sum_path, format!("{}  {}\n", archive_checksum, file_name)) {
+        eprintln!("Error: failed to write {}: {}", sum_path.display(), e);
+        exit(1);
+    }
+
+    Packaged {
+        name,
+        version,
+        description: config.package.description.clone(),
+        archive,
+        archive_checksum,
+        checksum,
+    }
+}
+
+/// Unpacks a package archive into `dst`, dropping the `<name>-<version>/`
+/// directory every entry sits under.
+pub fn unpack(archive: &Path, dst: &Path) -> io::Result<()> {
+    let mut tar = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let rel: PathBuf = path.components().skip(1).collect();
+        if rel.as_os_str().is_empty() {
+            continue;
+        }
+        // Don't let a crafted archive write outside `dst`.
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(io::Error::other(format!(
+                "refusing to unpack {}",
+                path.display()
+            )));
+        }
+        let out = dst.join(&rel);
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&out)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+// A registry is a directory, or a git repository holding one, with an
+// `index/` folder of `<name>.toml` files. Each file lists every published
+// version of one package, where to fetch it from and its content checksum,
+// so `foo = "1.2"` in Seastar.toml is enough to depend on it.
+
+use git2::Repository;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use super::package::Packaged;
+use super::resolver::Network;
+
+pub const INDEX_DIR: &str = "index";
+pub const PACKAGES_DIR: &str = "packages";
+const REGISTRY_CACHE_DIR: &str = "~/.seastar/package_cache/registries";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, rename = "version")]
+    pub versions: Vec<IndexVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexVersion {
+    pub version: Version,
+    #[serde(flatten)]
+    pub source: IndexSource,
+    /// Checksum of the package's contents, computed like Seastar.lock's.
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum IndexSource {
+    Git {
+        git: String,
+        rev: String,
+    },
+    /// A directory, relative to the registry root.
+    Path {
+        path: String,
+    },
+    /// A `seastar package` archive, relative to the registry root.
+    Archive {
+        archive: String,
+    },
+}
+
+impl IndexEntry {
+    pub fn latest(&self) -> Option<&IndexVersion> {
+        self.versions
+            .iter()
+            .max_by(|a, b| a.version.cmp(&b.version))
+    }
+}
+
+pub struct Registry {
+    pub location: String,
+    pub root: PathBuf,
+}
+
+/// The registry from `[registry] index` in the user config, if any.
+pub fn configured() -> Option<String> {
+    crate::io::load_user_config().registry.index
+}
+
+fn local_path(location: &str) -> PathBuf {
+    PathBuf::from(location.strip_prefix("file://").unwrap_or(location))
+}
+
+/// A `file://` URL or plain path that points at a local directory.
+fn local_dir(location: &str) -> Option<PathBuf> {
+    let path = local_path(location);
+    path.is_dir().then_some(path)
+}
+
+impl Registry {
+    /// Opens the registry at `location`. Local directories (including
+    /// `file://` URLs) are read in place, so they work without a network;
+    /// anything else is cloned into the package cache, and fetched again
+    /// when `network` is `Refresh`.
+    pub fn open(location: &str, network: Network) -> Registry {
+        if let Some(root) = local_dir(location) {
+            return Registry {
+                location: location.to_string(),
+                root,
+            };
+        }
+
+        let cache_dir = shellexpand::tilde(REGISTRY_CACHE_DIR).to_string();
+        let root = Path::new(&cache_dir).join(format!("{:x}", md5::compute(location)));
+        let result = if !root.exists() && network == Network::Offline {
+            eprintln!(
+                "Error: registry {} is not in the package cache and Seastar is offline\nRun `seastar fetch` while online to download it.",
+                location
+            );
+            exit(1);
+        } else if !root.exists() {
+            eprintln!("Cloning registry {}...", location);
+            Repository::clone(location, &root).map(|_| ())
+        } else if network == Network::Refresh {
+            eprintln!("Updating registry {}...", location);
+            Repository::open(&root).and_then(|repo| {
+                super::resolver::fetch_origin(&repo)?;
+                let head = repo
+                    .revparse_single("refs/remotes/origin/HEAD")?
+                    .peel_to_commit()?;
+                super::resolver::checkout_commit(&repo, head.id())
+            })
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            eprintln!("Error: failed to read registry {}: {}", location, e);
+            exit(1);
+        }
+
+        Registry {
+            location: location.to_string(),
+            root,
+        }
+    }
+
+    fn entry_path(&self, name: &str) -> PathBuf {
+        self.root.join(INDEX_DIR).join(format!("{}.toml", name))
+    }
+
+    pub fn entry(&self, name: &str) -> Option<IndexEntry> {
+        let path = self.entry_path(name);
+        let data = fs::read_to_string(&path).ok()?;
+        match toml::from_str(&data) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("Error: failed to parse {}: {}", path.display(), e);
+                exit(1);
+            }
+        }
+    }
+
+    /// Every package in the index, sorted by name.
+    pub fn entries(&self) -> Vec<IndexEntry> {
+        let mut names: Vec<String> = fs::read_dir(self.root.join(INDEX_DIR))
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let path = e.path();
+                (path.extension()? == "toml")
+                    .then(|| path.file_stem()?.to_str().map(String::from))
+                    .flatten()
+            })
+            .collect();
+        names.sort();
+        names.iter().filter_map(|name| self.entry(name)).collect()
+    }
+
+    /// Copies a packaged archive into `packages/<name>/` and adds the version
+    /// to `index/<name>.toml`. Publishing needs a local directory, which is
+    /// created as a new registry if it doesn't exist yet.
+    pub fn publish(location: &str, packaged: &Packaged) -> Result<(), String> {
+        if location.contains("://") && !location.starts_with("file://") {
+            return Err(format!(
+                "can only publish to a local directory, not {}",
+                location
+            ));
+        }
+        let root = local_path(location);
+        if root.exists() && !root.is_dir() {
+            return Err(format!("{} is not a directory", root.display()));
+        }
+        let registry = Registry {
+            location: location.to_string(),
+            root,
+        };
+
+        let mut entry = registry.entry(&packaged.name).unwrap_or(IndexEntry {
+            name: packaged.name.clone(),
+            description: None,
+            versions: Vec::new(),
+        });
+        if entry.versions.iter().any(|v| v.version == packaged.version) {
+            return Err(format!(
+                "{} {} is already published in {}",
+                packaged.name, packaged.version, location
+            ));
+        }
+
+        let file_name = packaged
+            .archive
+            .file_name()
+            .ok_or("package archive has no file name")?;
+        let rel = Path::new(PACKAGES_DIR).join(&packaged.name).join(file_name);
+        let dst = registry.root.join(&rel);
+        let entry_path = registry.entry_path(&packaged.name);
+        for dir in [dst.parent(), entry_path.parent()].into_iter().flatten() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::copy(&packaged.archive, &dst).map_err(|e| format!("{}: {}", dst.display(), e))?;
+        fs::write(
+            dst.with_extension("gz.sha256"),
+            format!(
+                "{}  {}\n",
+                packaged.archive_checksum,
+                file_name.to_string_lossy()
+            ),
+        )
+        .map_err(|e| format!("{}: {}", dst.display(), e))?;
+
+        if packaged.description.is_some() {
+            entry.description = packaged.description.clone();
+        }
+        entry.versions.push(IndexVersion {
+            version: packaged.version.clone(),
+            source: IndexSource::Archive {
+                archive: rel.to_string_lossy().replace('\\', "/"),
+            },
+            checksum: packaged.checksum.clone(),
+        });
+        entry.versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+        let data = toml::to_string(&entry).map_err(|e| e.to_string())?;
+        fs::write(&entry_path, data).map_err(|e| format!("{}: {}", entry_path.display(), e))
+    }
+}
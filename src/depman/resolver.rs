@@ -1,10 +1,19 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
     path::Path,
+    process::exit,
 };
 
-use git2::Repository;
+use super::features::{self, Features};
+use super::lockfile::{self, LockedPackage, Lockfile};
+use super::package;
+use super::registry::{self, IndexSource, Registry};
+use super::vendor::{self, VendorConfig};
 use crate::io::{Config, DepSpec};
+use crate::platform::{self, Platform};
+use git2::{Oid, Repository};
+use semver::{Version, VersionReq};
 
 const PACKAGE_CACHE_DIR: &str = "~/.seastar/package_cache/git_clones";
 
@@ -17,62 +26,305 @@ fn ensure_cache_dir() -> std::path::PathBuf {
     cache_path.to_path_buf()
 }
 
-fn get_cached_package_path(repo: &str, tag: Option<&str>) -> std::path::PathBuf {
+/// Each (repo, ref) pair gets its own clone. The ref kind is part of the
+/// name and the ref itself is escaped, so `branch = "v1"`, `tag = "v1"` and
+/// `branch = "a/b"` vs `branch = "a_b"` can't end up sharing a directory.
+fn get_cached_package_path(repo: &str, reference: &GitRef) -> std::path::PathBuf {
     let repo_hash = format!("{:x}", md5::compute(repo));
-    let mut cache_name = repo_hash;
-    if let Some(tag) = tag {
-        cache_name = format!("{}-{}", cache_name, tag);
-    }
+    let cache_name = match reference {
+        GitRef::DefaultBranch => repo_hash,
+        GitRef::Branch(name) => format!("{}-branch-{}", repo_hash, escape_ref(name)),
+        GitRef::Tag(name) => format!("{}-tag-{}", repo_hash, escape_ref(name)),
+        GitRef::Rev(rev) => format!("{}-rev-{}", repo_hash, escape_ref(rev)),
+        // Every clone has all the tags, so version requirements share one.
+        GitRef::Version(_) => format!("{}-versions", repo_hash),
+    };
     ensure_cache_dir().join(cache_name)
 }
 
-#[derive(Debug, Clone)]
+/// Percent-encodes anything that isn't safe in a single path component.
+fn escape_ref(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// What a git dependency follows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitRef {
+    /// Whatever the remote's HEAD points to.
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    /// An exact commit, or anything else `git rev-parse` understands.
+    Rev(String),
+    /// The newest tag matching a semver requirement.
+    Version(VersionReq),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DepSource {
-    Git { repo: String, tag: Option<String> },
+    Git { repo: String, reference: GitRef },
     Path(String),
+    Registry { index: String, version: VersionReq },
+}
+
+impl DepSource {
+    /// What makes two sources the same package. Version requirements are
+    /// left out: differing ones are a version conflict, not a source one.
+    fn identity(&self) -> String {
+        match self {
+            DepSource::Git {
+                repo,
+                reference: GitRef::Version(_),
+            } => format!("git+{}", repo.trim_end_matches('/')),
+            DepSource::Git { .. } => self.to_string(),
+            DepSource::Path(path) => {
+                let path = Path::new(path);
+                let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                format!("path+{}", path.display())
+            }
+            DepSource::Registry { index, .. } => format!("registry+{}", index),
+        }
+    }
+
+    /// How the source is written in Seastar.lock and vendor/config.toml.
+    /// Version requirements are left out, since they depend on which
+    /// dependent reached the package first; the pinned version says what
+    /// was picked.
+    pub fn lock_source(&self) -> String {
+        match self {
+            DepSource::Git {
+                repo,
+                reference: GitRef::Version(_),
+            } => format!("git+{}?{}", repo, lockfile::VERSIONS_QUERY),
+            DepSource::Registry { index, .. } => format!("registry+{}", index),
+            _ => self.to_string(),
+        }
+    }
+
+    /// The semver requirement this source is resolved against, if any.
+    fn version_req(&self) -> Option<&VersionReq> {
+        match self {
+            DepSource::Git {
+                reference: GitRef::Version(req),
+                ..
+            }
+            | DepSource::Registry { version: req, .. } => Some(req),
+            _ => None,
+        }
+    }
+}
+
+/// The source as written in Seastar.toml, e.g. `git+https://host/repo?tag=v1`
+/// or `git+https://host/repo?version=^1.2`.
+impl fmt::Display for DepSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DepSource::Git { repo, reference } => match reference {
+                GitRef::DefaultBranch => write!(f, "git+{}", repo),
+                GitRef::Branch(name) => write!(f, "git+{}?branch={}", repo, name),
+                GitRef::Tag(name) => write!(f, "git+{}?tag={}", repo, name),
+                GitRef::Rev(rev) => write!(f, "git+{}?rev={}", repo, rev),
+                GitRef::Version(req) => write!(f, "git+{}?version={}", repo, req),
+            },
+            DepSource::Path(path) => write!(f, "path+{}", path),
+            DepSource::Registry { index, version } => {
+                write!(f, "registry+{}?version={}", index, version)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Dep {
     pub name: String,
     pub source: DepSource,
+    /// The features the dependent asks for.
+    pub features: BTreeSet<String>,
+}
+
+/// Why a package is in the graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepKind {
+    /// Reachable through `[dependencies]`, so linked into everything.
+    Normal,
+    /// Only reachable through the root's `[dev-dependencies]`, so only
+    /// built for tests and examples.
+    Dev,
 }
 
 #[derive(Debug)]
 pub struct DepGraph {
     pub nodes: HashMap<String, DepNode>,
+    /// The root package's own dependencies, of either kind.
+    pub root_deps: Vec<String>,
+    /// What the root package's features turn on.
+    pub root_features: Features,
 }
 
 #[derive(Debug)]
 pub struct DepNode {
     pub dep: Dep,
     pub dependencies: Vec<String>, // I might want to use DepNode instead, this was easier
+    /// The commit a git dependency was checked out at.
+    pub commit: Option<String>,
+    /// The version a `version = "..."` requirement resolved to.
+    pub version: Option<Version>,
+    pub checksum: String,
+    /// The dependency's own Seastar.toml.
+    pub config: Config,
+    /// The source the graph asked for, when `[patch]` or `[replace]` swapped
+    /// it for `dep.source`.
+    pub patched_from: Option<DepSource>,
+    pub kind: DepKind,
+    /// What the features every dependent asked for turn on.
+    pub features: Features,
+}
+
+/// A package's `[dependencies]`, with the optional ones `features` turns on,
+/// or all of them with `all_optional`.
+pub fn parse_deps(config: &Config, features: &Features, all_optional: bool) -> Vec<Dep> {
+    parse_specs(&config.dependencies, |name, spec| {
+        !spec.is_optional() || all_optional || features.deps.contains(name)
+    })
 }
 
-pub fn parse_deps(config: &Config) -> Vec<Dep> {
+/// What the root package's features turn on: the ones from `--features`,
+/// plus `default` unless `--no-default-features` was given.
+pub fn root_features(config: &Config, options: &ResolveOptions) -> Features {
+    let requested = features::requested(&options.features, !options.no_default_features);
+    features::resolve(config, &requested).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        exit(1);
+    })
+}
+
+/// The root package's `[dependencies]` and `[dev-dependencies]`, each with
+/// the optional ones its features turn on.
+fn root_deps(
+    config: &Config,
+    features: &Features,
+    options: &ResolveOptions,
+) -> Vec<(Dep, DepKind)> {
+    let enabled = |name: &str, spec: &DepSpec| {
+        !spec.is_optional() || options.all_optional || features.deps.contains(name)
+    };
+    let normal = parse_specs(&config.dependencies, enabled)
+        .into_iter()
+        .map(|dep| (dep, DepKind::Normal));
+    let dev = parse_specs(&config.dev_dependencies, enabled)
+        .into_iter()
+        .map(|dep| (dep, DepKind::Dev));
+    normal.chain(dev).collect()
+}
+
+/// A package's optional dependencies that `features` leaves off.
+fn disabled_optional<'a>(
+    config: &'a Config,
+    features: &'a Features,
+) -> impl Iterator<Item = String> + 'a {
+    config
+        .dependencies
+        .iter()
+        .chain(&config.dev_dependencies)
+        .filter(|(name, spec)| spec.is_optional() && !features.deps.contains(*name))
+        .map(|(name, _)| name.clone())
+}
+
+fn parse_specs(
+    specs: &HashMap<String, DepSpec>,
+    wanted: impl Fn(&str, &DepSpec) -> bool,
+) -> Vec<Dep> {
     let mut deps: Vec<Dep> = Vec::new();
-    for (dep_name, dep_spec) in &config.dependencies {
+    for (dep_name, dep_spec) in specs {
+        if !wanted(dep_name, dep_spec) {
+            continue;
+        }
+        let features = match dep_spec {
+            DepSpec::Simple(_) => features::requested(&[], true),
+            DepSpec::Detailed {
+                features,
+                default_features,
+                ..
+            } => features::requested(features, *default_features),
+        };
         match dep_spec {
-            DepSpec::Simple(url) => deps.push(Dep {
+            DepSpec::Simple(spec) => deps.push(Dep {
                 name: dep_name.to_string(),
-                source: DepSource::Git {
-                    repo: url.to_string(),
-                    tag: None,
+                features,
+                source: match VersionReq::parse(spec) {
+                    Ok(version) => registry_source(version),
+                    Err(_) => DepSource::Git {
+                        repo: spec.to_string(),
+                        reference: GitRef::DefaultBranch,
+                    },
                 },
             }),
-            DepSpec::Detailed { git, tag, path } => {
+            DepSpec::Detailed {
+                git,
+                branch,
+                tag,
+                rev,
+                version,
+                path,
+                ..
+            } => {
+                let refs: Vec<GitRef> = [
+                    branch.clone().map(GitRef::Branch),
+                    tag.clone().map(GitRef::Tag),
+                    rev.clone().map(GitRef::Rev),
+                    version.clone().map(GitRef::Version),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                if refs.len() > 1 {
+                    eprintln!(
+                        "Error: dependency '{}' may only set one of `branch`, `tag`, `rev` and `version`",
+                        dep_name
+                    );
+                    exit(1);
+                }
+                if git.is_none() && (branch.is_some() || tag.is_some() || rev.is_some()) {
+                    eprintln!(
+                        "Error: dependency '{}' sets `branch`, `tag` or `rev` without `git`",
+                        dep_name
+                    );
+                    exit(1);
+                }
+
+                if let (None, None, Some(version)) = (git, path, version) {
+                    deps.push(Dep {
+                        name: dep_name.to_string(),
+                        features: features.clone(),
+                        source: registry_source(version.clone()),
+                    });
+                } else if git.is_none() && version.is_some() {
+                    eprintln!(
+                        "Error: dependency '{}' can't combine `version` with `path`",
+                        dep_name
+                    );
+                    exit(1);
+                }
+
                 if let Some(git) = git {
                     deps.push(Dep {
                         name: dep_name.to_string(),
+                        features: features.clone(),
                         source: DepSource::Git {
                             repo: git.to_string(),
-                            tag: tag.clone(),
+                            reference: refs.into_iter().next().unwrap_or(GitRef::DefaultBranch),
                         },
                     });
                 }
                 if let Some(path) = path {
                     deps.push(Dep {
                         name: dep_name.to_string(),
+                        features: features.clone(),
                         source: DepSource::Path(path.to_string()),
                     });
                 }
@@ -80,124 +332,999 @@ pub fn parse_deps(config: &Config) -> Vec<Dep> {
         }
     }
 
+    // Sorted, so the graph is walked (and the first requirement to reach a
+    // package picked) the same way on every run.
+    deps.sort_by(|a, b| a.name.cmp(&b.name));
     deps
 }
 
-pub fn resolve_and_fetch(deps: &[Dep], dep_dir: &str) -> DepGraph {
-    let mut graph = DepGraph {
-        nodes: HashMap::new(),
+/// The root package's `[patch."<url>"]` and `[replace]` tables. A patch swaps
+/// the source of a package wherever the graph asks for it from `<url>` (a
+/// git URL or registry index); a replacement swaps it whatever the source.
+#[derive(Debug, Default)]
+struct Patches {
+    /// Keyed by URL, then package name.
+    patch: HashMap<String, HashMap<String, Dep>>,
+    replace: HashMap<String, Dep>,
+}
+
+fn trim_url(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+impl Patches {
+    fn parse(config: &Config) -> Patches {
+        let single = |name: &str, deps: Vec<Dep>, table: &str| -> Dep {
+            match <[Dep; 1]>::try_from(deps) {
+                Ok([dep]) => dep,
+                Err(_) => {
+                    eprintln!(
+                        "Error: {} entry '{}' must have exactly one source",
+                        table, name
+                    );
+                    exit(1);
+                }
+            }
+        };
+
+        let mut patches = Patches::default();
+        for (url, specs) in &config.patch {
+            let table = format!("[patch.\"{}\"]", url);
+            let deps = specs
+                .iter()
+                .map(|(name, spec)| {
+                    let specs = HashMap::from([(name.clone(), spec.clone())]);
+                    (
+                        name.clone(),
+                        single(name, parse_specs(&specs, |_, _| true), &table),
+                    )
+                })
+                .collect();
+            patches.patch.insert(trim_url(url).to_string(), deps);
+        }
+        for (name, spec) in &config.replace {
+            let specs = HashMap::from([(name.clone(), spec.clone())]);
+            let dep = single(name, parse_specs(&specs, |_, _| true), "[replace]");
+            patches.replace.insert(name.clone(), dep);
+        }
+        patches
+    }
+
+    /// What to use instead of `dep`, if anything. `[replace]` wins over
+    /// `[patch]`.
+    fn apply(&self, dep: &Dep) -> Option<&Dep> {
+        let url = match &dep.source {
+            DepSource::Git { repo, .. } => Some(repo),
+            DepSource::Registry { index, .. } => Some(index),
+            DepSource::Path(_) => None,
+        };
+        self.replace
+            .get(&dep.name)
+            .or_else(|| self.patch.get(trim_url(url?))?.get(&dep.name))
+            .filter(|patched| patched.source != dep.source)
+    }
+
+    /// Patches that matched nothing, as `(table, name)`.
+    fn unused<'a>(&'a self, graph: &DepGraph) -> Vec<(String, &'a str)> {
+        let used = |name: &str, source: &DepSource| {
+            graph
+                .nodes
+                .get(name)
+                .is_some_and(|n| n.patched_from.is_some() && &n.dep.source == source)
+        };
+        let mut unused: Vec<(String, &str)> = self
+            .patch
+            .iter()
+            .flat_map(|(url, deps)| {
+                deps.iter()
+                    .filter(|(name, dep)| !used(name, &dep.source))
+                    .map(move |(name, _)| (format!("[patch.\"{}\"]", url), name.as_str()))
+            })
+            .chain(
+                self.replace
+                    .iter()
+                    .filter(|(name, dep)| !used(name, &dep.source))
+                    .map(|(name, _)| ("[replace]".to_string(), name.as_str())),
+            )
+            .collect();
+        unused.sort();
+        unused
+    }
+}
+
+/// A dependency on the configured registry. Nothing fails here if there is
+/// none: a vendored project names the registry it was vendored from, and
+/// `fetch_registry` reports a missing one only when it has to be used.
+fn registry_source(version: VersionReq) -> DepSource {
+    let index = registry::configured()
+        .or_else(vendor::registry_index)
+        .unwrap_or_default();
+    DepSource::Registry { index, version }
+}
+
+/// Which Seastar.lock pins `resolve_and_fetch` may ignore.
+#[derive(Debug, Clone, Default)]
+pub enum Unlock {
+    /// Use every pin that still matches Seastar.toml.
+    #[default]
+    Nothing,
+    /// Re-resolve everything, as if there were no lockfile.
+    All,
+    /// Re-resolve only these packages.
+    Packages(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    pub unlock: Unlock,
+    /// Resolve strictly from the package cache, never touching the network.
+    pub offline: bool,
+    /// Fetch as usual even when vendor/config.toml exists.
+    pub ignore_vendor: bool,
+    /// Features of the root package to turn on.
+    pub features: Vec<String>,
+    /// Leave the root package's `default` feature off.
+    pub no_default_features: bool,
+    /// Take in every optional dependency, whatever the features say, so
+    /// `fetch` and `vendor` cover any build.
+    pub all_optional: bool,
+    /// Take in dependencies' `[target]` dependencies for every platform, not
+    /// just `target`. The root's are the caller's to add.
+    pub all_targets: bool,
+    /// Triple that `[target]` tables are evaluated against. Detected from
+    /// the root package's compiler when unset.
+    pub target: Option<String>,
+}
+
+/// How much a fetch may use the network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Network {
+    /// Only what's already in the package cache.
+    Offline,
+    /// Clone what's missing and fetch commits the cache doesn't have.
+    Online,
+    /// Like `Online`, but also fetch cached clones from origin first.
+    Refresh,
+}
+
+impl ResolveOptions {
+    fn is_unlocked(&self, name: &str) -> bool {
+        match &self.unlock {
+            Unlock::Nothing => false,
+            Unlock::All => true,
+            Unlock::Packages(names) => names.iter().any(|n| n == name),
+        }
+    }
+
+    fn network(&self, name: &str) -> Network {
+        if self.offline {
+            Network::Offline
+        } else if self.is_unlocked(name) {
+            Network::Refresh
+        } else {
+            Network::Online
+        }
+    }
+}
+
+/// Resolves and fetches the whole graph, pinning it in Seastar.lock: pins
+/// from an existing lockfile are honoured, and new packages get pinned to
+/// whatever they resolve to now. `config` is the root package's manifest,
+/// whose `[patch]` and `[replace]` tables apply to the whole graph.
+pub fn resolve_and_fetch(config: &Config, dep_dir: &str, options: &ResolveOptions) -> DepGraph {
+    let root_features = root_features(config, options);
+    let deps = root_deps(config, &root_features, options);
+    let patches = Patches::parse(config);
+    let lock_path = Path::new(lockfile::LOCKFILE_PATH);
+    let lock = lockfile::load(lock_path);
+
+    let vendored = if options.ignore_vendor {
+        None
+    } else {
+        vendor::load()
     };
-    let mut visited = HashSet::new();
+    let platform = platform::detect(options.target.as_deref(), &config.package.compiler);
+
+    let mut resolver = Resolver::new(
+        dep_dir,
+        &lock,
+        options,
+        &patches,
+        vendored.as_ref(),
+        &platform,
+    );
+    loop {
+        for (dep, _) in &deps {
+            resolver.resolve(dep, &[]);
+        }
+        if !resolver.restart {
+            break;
+        }
+        resolver.start_over();
+    }
+    let mut graph = resolver.graph;
+    graph.root_deps = deps.iter().map(|(dep, _)| dep.name.clone()).collect();
+    graph.root_features = root_features;
+    let normal: Vec<String> = deps
+        .iter()
+        .filter(|(_, kind)| *kind == DepKind::Normal)
+        .map(|(dep, _)| dep.name.clone())
+        .collect();
+    graph.assign_kinds(&normal);
+
+    for (table, name) in patches.unused(&graph) {
+        eprintln!(
+            "Warning: {} entry '{}' was not used; nothing in the graph matches it",
+            table, name
+        );
+    }
 
-    for dep in deps {
-        resolve_dep_recursive(dep, dep_dir, &mut graph, &mut visited);
+    let disabled = disabled_optional(config, &graph.root_features)
+        .chain(platform::inactive_deps(config, &platform))
+        .chain(graph.nodes.values().flat_map(|node| {
+            disabled_optional(&node.config, &node.features)
+                .chain(platform::inactive_deps(&node.config, &platform))
+        }))
+        .collect();
+    let new_lock = keep_disabled_pins(graph.to_lockfile(), &lock, disabled);
+    if new_lock != lock && (lock_path.exists() || !graph.nodes.is_empty()) {
+        lockfile::save(lock_path, &new_lock);
     }
 
     graph
 }
 
-fn resolve_dep_recursive(
-    dep: &Dep,
-    dep_dir: &str,
-    graph: &mut DepGraph,
-    visited: &mut HashSet<String>,
-) {
-    if visited.contains(&dep.name) {
-        return;
+/// Carries over the pins of optional dependencies that are off this time,
+/// and of those only other platforms use, along with whatever only they pull
+/// in, so turning a feature back on or building for another target doesn't
+/// move them.
+fn keep_disabled_pins(new_lock: Lockfile, old_lock: &Lockfile, disabled: Vec<String>) -> Lockfile {
+    let mut packages = new_lock.packages;
+    let mut pending = disabled;
+    while let Some(name) = pending.pop() {
+        if packages.iter().any(|p| p.name == name) {
+            continue;
+        }
+        if let Some(old) = old_lock.packages.iter().find(|p| p.name == name) {
+            pending.extend(old.dependencies.iter().cloned());
+            packages.push(old.clone());
+        }
     }
-    visited.insert(dep.name.clone());
+    Lockfile::new(packages)
+}
 
-    fetch(dep, dep_dir);
+/// The package that asked for the last package in `chain`.
+fn requester(chain: &[String]) -> &str {
+    match chain.len() {
+        0 | 1 => "the root package",
+        n => &chain[n - 2],
+    }
+}
 
-    let dep_config = load_dep_config(dep, dep_dir);
-    println!("{:?}", dep_config);
-    let child_deps = parse_deps(&dep_config);
+/// A `version = "..."` requirement and the chain of packages it came from,
+/// starting below the root.
+struct Requirement {
+    path: Vec<String>,
+    req: VersionReq,
+}
 
-    let mut dependencies = Vec::new();
-    for child in &child_deps {
-        dependencies.push(child.name.clone());
-        resolve_dep_recursive(child, dep_dir, graph, visited);
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "root -> {} {}", self.path.join(" -> "), self.req)
     }
+}
 
-    graph.nodes.insert(
-        dep.name.clone(),
-        DepNode {
-            dep: dep.clone(),
-            dependencies,
-        },
-    );
+/// Walks the graph depth-first. Versioned packages are fixed by the first
+/// requirement that reaches them; if a later one disagrees, the resolver
+/// looks for a version that satisfies everything seen so far in this pass
+/// and, if there is one, starts over with that version forced. A forced
+/// version that runs into a conflict of its own isn't forced again, so this
+/// always ends.
+struct Resolver<'a> {
+    dep_dir: &'a str,
+    lock: &'a Lockfile,
+    options: &'a ResolveOptions,
+    patches: &'a Patches,
+    /// Sources to take from vendor/ instead of fetching.
+    vendored: Option<&'a VendorConfig>,
+    /// What dependencies' `[target]` tables are evaluated against.
+    platform: &'a Platform,
+    graph: DepGraph,
+    /// Where each package was first reached from, and with which source.
+    visited: HashMap<String, (Vec<String>, DepSource)>,
+    /// The version picked for each versioned package in this pass.
+    chosen: HashMap<String, Version>,
+    /// Every version tag of each versioned package.
+    available: HashMap<String, Vec<Version>>,
+    /// Requirements seen so far in this pass.
+    requirements: HashMap<String, Vec<Requirement>>,
+    /// Features asked of each package so far. Unlike the rest, these survive
+    /// a restart.
+    features: HashMap<String, BTreeSet<String>>,
+    /// Versions an earlier pass settled on after a conflict.
+    forced: HashMap<String, Version>,
+    /// Forced versions that turned out to conflict too.
+    rejected: HashMap<String, Vec<Version>>,
+    restart: bool,
 }
 
-fn load_dep_config(dep: &Dep, dep_dir: &str) -> Config {
+impl<'a> Resolver<'a> {
+    fn new(
+        dep_dir: &'a str,
+        lock: &'a Lockfile,
+        options: &'a ResolveOptions,
+        patches: &'a Patches,
+        vendored: Option<&'a VendorConfig>,
+        platform: &'a Platform,
+    ) -> Self {
+        Resolver {
+            dep_dir,
+            lock,
+            options,
+            patches,
+            vendored,
+            platform,
+            graph: DepGraph {
+                nodes: HashMap::new(),
+                root_deps: Vec::new(),
+                root_features: Features::default(),
+            },
+            visited: HashMap::new(),
+            chosen: HashMap::new(),
+            available: HashMap::new(),
+            requirements: HashMap::new(),
+            features: HashMap::new(),
+            forced: HashMap::new(),
+            rejected: HashMap::new(),
+            restart: false,
+        }
+    }
+
+    fn start_over(&mut self) {
+        self.graph.nodes.clear();
+        self.visited.clear();
+        self.chosen.clear();
+        self.requirements.clear();
+        self.restart = false;
+    }
+
+    /// `path` is the chain of packages that led to `dep`, not including it.
+    fn resolve(&mut self, dep: &Dep, path: &[String]) {
+        if self.restart {
+            return;
+        }
+        // A patched package still gets the features its dependent asked for.
+        let patched = self.patches.apply(dep).map(|patch| Dep {
+            features: dep.features.clone(),
+            ..patch.clone()
+        });
+        let patched_from = patched.as_ref().map(|_| dep.source.clone());
+        let dep = patched.as_ref().unwrap_or(dep);
+        let mut chain = path.to_vec();
+        chain.push(dep.name.clone());
+
+        if let Some(start) = path.iter().position(|name| name == &dep.name) {
+            eprintln!("Error: dependency cycle: {}", chain[start..].join(" -> "));
+            exit(1);
+        }
+
+        if let Some((first_path, first_source)) = self.visited.get(&dep.name)
+            && first_source.identity() != dep.source.identity()
+        {
+            eprintln!(
+                "Error: '{}' is required from two different sources:",
+                dep.name
+            );
+            eprintln!(
+                "    {} requires it from {}",
+                requester(first_path),
+                first_source
+            );
+            eprintln!("    {} requires it from {}", requester(&chain), dep.source);
+            exit(1);
+        }
+
+        if let Some(req) = dep.source.version_req() {
+            let requirements = self.requirements.entry(dep.name.clone()).or_default();
+            if !requirements
+                .iter()
+                .any(|r| r.path == chain && &r.req == req)
+            {
+                requirements.push(Requirement {
+                    path: chain.clone(),
+                    req: req.clone(),
+                });
+            }
+            if self.chosen.get(&dep.name).is_some_and(|v| !req.matches(v)) {
+                self.settle_conflict(&dep.name);
+                return;
+            }
+        }
+
+        // Features are unified: a package gets every feature anyone asks for,
+        // so a new request for one that's already resolved means starting over.
+        let requested = self.features.entry(dep.name.clone()).or_default();
+        if !dep.features.is_subset(requested) {
+            requested.extend(dep.features.iter().cloned());
+            if self.visited.contains_key(&dep.name) {
+                self.restart = true;
+                return;
+            }
+        }
+
+        if self.visited.contains_key(&dep.name) {
+            return;
+        }
+        self.visited
+            .insert(dep.name.clone(), (chain.clone(), dep.source.clone()));
+
+        let forced = self.forced.get(&dep.name);
+        let unlocked = self.options.is_unlocked(&dep.name);
+        let pinned = self
+            .lock
+            .find(&dep.name, &dep.source.lock_source())
+            .filter(|_| !unlocked && forced.is_none());
+
+        let locked_version = pinned
+            .and_then(|p| p.version.as_deref())
+            .and_then(|v| Version::parse(v).ok());
+        let pin = match (forced, &dep.source, pinned) {
+            (Some(version), _, _) => Pin::Version(version),
+            (None, DepSource::Registry { .. }, Some(_)) => {
+                locked_version.as_ref().map_or(Pin::Latest, Pin::Version)
+            }
+            (None, _, Some(pinned)) => pinned.commit.as_deref().map_or(Pin::Latest, Pin::Commit),
+            (None, _, None) => Pin::Latest,
+        };
+        let fetched = match self.vendored {
+            Some(vendored) if !matches!(dep.source, DepSource::Path(_)) => {
+                fetch_vendored(dep, vendored, self.dep_dir)
+            }
+            _ => fetch(dep, self.dep_dir, pin, self.options.network(&dep.name)),
+        };
+        let checksum = lockfile::checksum_dir(&Path::new(self.dep_dir).join(&dep.name));
+
+        // Path dependencies are expected to change under us; others are not.
+        if let Some(pinned) = pinned
+            && !matches!(dep.source, DepSource::Path(_))
+            && pinned.checksum != checksum
+        {
+            eprintln!(
+                "Error: checksum mismatch for {} at {}\n  Seastar.lock: {}\n  fetched:      {}\nIf the change is expected, run `seastar update {}`.",
+                dep.name,
+                fetched.commit.as_deref().unwrap_or("?"),
+                pinned.checksum,
+                checksum,
+                dep.name
+            );
+            exit(1);
+        }
+
+        let version = fetched.version.or(locked_version);
+        if let Some(version) = &version {
+            self.chosen.insert(dep.name.clone(), version.clone());
+            self.available
+                .insert(dep.name.clone(), fetched.available.clone());
+        }
+
+        let dep_config =
+            load_dep_config(dep, self.dep_dir, self.platform, self.options.all_targets);
+        let features =
+            features::resolve(&dep_config, &self.features[&dep.name]).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                exit(1);
+            });
+        let child_deps = parse_deps(&dep_config, &features, self.options.all_optional);
+
+        let mut dependencies = Vec::new();
+        for child in &child_deps {
+            dependencies.push(child.name.clone());
+            self.resolve(child, &chain);
+        }
+
+        self.graph.nodes.insert(
+            dep.name.clone(),
+            DepNode {
+                dep: dep.clone(),
+                dependencies,
+                commit: fetched.commit,
+                version,
+                checksum,
+                config: dep_config,
+                patched_from,
+                kind: DepKind::Dev,
+                features,
+            },
+        );
+    }
+
+    /// Called when a requirement on `name` isn't met by the version already
+    /// picked. Either forces a version that works for everyone and restarts,
+    /// or gives up and shows who asked for what.
+    fn settle_conflict(&mut self, name: &str) {
+        if let Some(forced) = self.forced.get(name) {
+            self.rejected
+                .entry(name.to_string())
+                .or_default()
+                .push(forced.clone());
+        }
+        let rejected = self.rejected.get(name).map(Vec::as_slice).unwrap_or(&[]);
+        let requirements = &self.requirements[name];
+        let available = self.available.get(name).map(Vec::as_slice).unwrap_or(&[]);
+        let candidate = available
+            .iter()
+            .filter(|v| requirements.iter().all(|r| r.req.matches(v)))
+            .filter(|v| !rejected.contains(v))
+            .max();
+
+        match candidate {
+            Some(version) => {
+                self.forced.insert(name.to_string(), version.clone());
+                self.restart = true;
+            }
+            None => {
+                eprintln!(
+                    "Error: no version of '{}' satisfies every requirement in the graph:",
+                    name
+                );
+                for requirement in requirements {
+                    eprintln!("    {}", requirement);
+                }
+                eprintln!("Available versions: {}", list_versions(available));
+                exit(1);
+            }
+        }
+    }
+}
+
+fn load_dep_config(dep: &Dep, dep_dir: &str, platform: &Platform, all_targets: bool) -> Config {
     let path = format!("{}/{}/Seastar.toml", dep_dir, dep.name);
-    crate::io::load_config(path)
+    let mut config = crate::io::load_config(path);
+    if !config.build_dependencies.is_empty() {
+        eprintln!(
+            "Warning: ignoring the [build-dependencies] of '{}', which Seastar doesn't support yet",
+            dep.name
+        );
+    }
+    platform::apply(&mut config, platform);
+    if all_targets {
+        platform::include_inactive(&mut config, platform);
+    }
+    config
+}
+
+/// Every tag that reads as a semver version, with or without a leading `v`.
+fn version_tags(repo: &Repository) -> Result<Vec<(Version, String)>, git2::Error> {
+    Ok(repo
+        .tag_names(None)?
+        .iter()
+        .flatten()
+        .filter_map(|tag| {
+            let version = Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()?;
+            Some((version, tag.to_string()))
+        })
+        .collect())
+}
+
+/// The newest tag matching `req`, or exactly `want` if given.
+fn resolve_version(
+    repo: &Repository,
+    req: &VersionReq,
+    want: Option<&Version>,
+) -> Result<(Oid, Version), git2::Error> {
+    let (version, tag) = version_tags(repo)?
+        .into_iter()
+        .filter(|(version, _)| match want {
+            Some(want) => version == want,
+            None => req.matches(version),
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .ok_or_else(|| git2::Error::from_str("no matching tag"))?;
+    let commit = repo
+        .revparse_single(&format!("refs/tags/{}", tag))?
+        .peel_to_commit()?
+        .id();
+    Ok((commit, version))
+}
+
+/// The commit a dependency should use when nothing is pinned. A rev that
+/// isn't in the clone yet is worth one fetch before giving up, unless
+/// we're offline.
+fn resolve_ref(
+    repo: &Repository,
+    reference: &GitRef,
+    network: Network,
+) -> Result<Oid, git2::Error> {
+    let object = match reference {
+        GitRef::Version(req) => return Ok(resolve_version(repo, req, None)?.0),
+        GitRef::DefaultBranch => repo
+            .revparse_single("refs/remotes/origin/HEAD")
+            .or_else(|_| repo.revparse_single("HEAD")),
+        GitRef::Branch(name) => repo.revparse_single(&format!("refs/remotes/origin/{}", name)),
+        GitRef::Tag(name) => repo.revparse_single(&format!("refs/tags/{}", name)),
+        GitRef::Rev(rev) => repo.revparse_single(rev).or_else(|_| {
+            fetch_if_online(repo, network)?;
+            repo.revparse_single(rev)
+        }),
+    }?;
+    Ok(object.peel_to_commit()?.id())
+}
+
+/// Brings a cached clone up to date with every branch and tag on origin.
+/// Tags are force-updated too, in case one was moved upstream.
+pub fn fetch_origin(repo: &Repository) -> Result<(), git2::Error> {
+    repo.find_remote("origin")?.fetch(
+        &[
+            "+refs/heads/*:refs/remotes/origin/*",
+            "+refs/tags/*:refs/tags/*",
+        ],
+        None,
+        None,
+    )
+}
+
+const NOT_CACHED: &str = "not in the package cache and Seastar is offline";
+
+/// `fetch_origin`, or an error saying why not when we're offline.
+fn fetch_if_online(repo: &Repository, network: Network) -> Result<(), git2::Error> {
+    if network == Network::Offline {
+        return Err(git2::Error::from_str(NOT_CACHED));
+    }
+    fetch_origin(repo)
+}
+
+/// Makes sure `commit` is in the clone, fetching from origin if it isn't.
+fn ensure_commit(repo: &Repository, commit: Oid, network: Network) -> Result<(), git2::Error> {
+    if repo.find_commit(commit).is_ok() {
+        return Ok(());
+    }
+    fetch_if_online(repo, network)?;
+    repo.find_commit(commit).map(|_| ())
+}
+
+pub fn checkout_commit(repo: &Repository, commit: Oid) -> Result<(), git2::Error> {
+    repo.set_head_detached(commit)?;
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+}
+
+/// Copies a package into `dep_dir`, replacing whatever was there so files
+/// removed upstream don't linger.
+fn copy_to_dep_dir(src: &Path, dst: &Path) {
+    if dst.exists() {
+        let _ = std::fs::remove_dir_all(dst);
+    }
+    std::fs::create_dir_all(dst).expect("Failed to create destination directory");
+    if let Err(e) = super::fs_copy::copy_dir_recursive(src, dst) {
+        eprintln!("Error: failed to copy {}: {}", src.display(), e);
+        exit(1);
+    }
+}
+
+/// What to check a git dependency out at.
+enum Pin<'a> {
+    /// Whatever its ref resolves to now.
+    Latest,
+    /// A commit from Seastar.lock.
+    Commit(&'a str),
+    /// An exact version, for versioned dependencies.
+    Version(&'a Version),
+}
+
+#[derive(Default)]
+struct Fetched {
+    commit: Option<String>,
+    /// The version picked for a `version = "..."` requirement.
+    version: Option<Version>,
+    /// Every version tag in the repository, for versioned dependencies.
+    available: Vec<Version>,
 }
 
-fn fetch(dep: &Dep, dep_dir: &str) {
+/// Fetches a dependency into `dep_dir`, checking git dependencies out at
+/// `pin`. `network` says whether cached clones are fetched from origin
+/// first, or whether only the package cache may be used. Progress goes to
+/// stderr, so `seastar tree --format json` and the like keep stdout clean.
+fn fetch(dep: &Dep, dep_dir: &str, pin: Pin, network: Network) -> Fetched {
+    let dst = Path::new(dep_dir).join(&dep.name);
     match &dep.source {
-        DepSource::Git { repo, tag } => {
-            let dst = Path::new(dep_dir).join(&dep.name);
-            let cache_path = get_cached_package_path(repo, tag.as_deref());
-            
-            if !cache_path.exists() {
-                println!("Cloning {} to cache...", repo);
-
-                let repo = match Repository::clone(repo, &cache_path) {
-                    Ok(repo) => repo,
-                    Err(e) => {
-                        eprintln!("Failed to clone repository {}: {}", repo, e);
-                        return;
-                    }
-                };
-
-                if let Some(tag) = tag {
-                    let obj = match repo.revparse_single(&format!("refs/tags/{}", tag)) {
-                        Ok(obj) => obj,
-                        Err(e) => {
-                            eprintln!("Failed to find tag {}: {}", tag, e);
-                            return;
-                        }
-                    };
-
-                    let branch_name = format!("seastar/{}", tag);
-                    match repo.branch(&branch_name, &obj.peel_to_commit().unwrap(), true) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            eprintln!("Failed to create branch from tag {}: {}", tag, e);
-                            return;
-                        }
-                    };
-
-                    let mut head = repo.head().unwrap();
-                    head.set_target(obj.id(), "checkout tag").unwrap();
-                    repo.set_head(&format!("refs/heads/{}", branch_name)).unwrap();
-                    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
-                }
-            } else {
-                println!("Using cached copy of {}", repo);
-            }
+        DepSource::Git {
+            repo: url,
+            reference,
+        } => fetch_git(dep, url, reference, pin, network, &dst),
+        DepSource::Registry { index, version } => {
+            fetch_registry(dep, index, version, pin, network, &dst)
+        }
+        DepSource::Path(path) => {
+            copy_to_dep_dir(Path::new(path), &dst);
+            Fetched::default()
+        }
+    }
+}
 
-            if !dst.exists() {
-                std::fs::create_dir_all(&dst).expect("Failed to create destination directory");
+/// Takes a package from vendor/ instead of the package cache. The vendored
+/// copy is the only version there is, so a requirement it doesn't meet is
+/// reported as a conflict.
+fn fetch_vendored(dep: &Dep, vendored: &VendorConfig, dep_dir: &str) -> Fetched {
+    let source = dep.source.lock_source();
+    let Some(entry) = vendored.source.get(&source) else {
+        eprintln!(
+            "Error: '{}' ({}) is not in vendor/\nRun `seastar vendor` again to add it.",
+            dep.name, source
+        );
+        exit(1);
+    };
+    eprintln!("Using vendored copy of {}", dep.name);
+    copy_to_dep_dir(&entry.path(), &Path::new(dep_dir).join(&dep.name));
+    Fetched {
+        commit: entry.commit.clone(),
+        version: entry.version.clone(),
+        available: entry.version.iter().cloned().collect(),
+    }
+}
+
+fn fetch_git(
+    dep: &Dep,
+    url: &str,
+    reference: &GitRef,
+    pin: Pin,
+    network: Network,
+    dst: &Path,
+) -> Fetched {
+    let cache_path = get_cached_package_path(url, reference);
+
+    let repo = if !cache_path.exists() {
+        if network == Network::Offline {
+            eprintln!(
+                "Error: '{}' ({}) is not in the package cache and Seastar is offline\nRun `seastar fetch` while online to download it.",
+                dep.name, url
+            );
+            exit(1);
+        }
+        eprintln!("Cloning {} to cache...", url);
+        match Repository::clone(url, &cache_path) {
+            Ok(repo) => repo,
+            Err(e) => {
+                eprintln!("Error: failed to clone repository {}: {}", url, e);
+                exit(1);
             }
+        }
+    } else {
+        let repo = Repository::open(&cache_path).unwrap_or_else(|e| {
+            eprintln!(
+                "Error: cached clone {} is broken ({}); delete it and try again",
+                cache_path.display(),
+                e
+            );
+            exit(1);
+        });
+        if network == Network::Refresh {
+            eprintln!("Fetching updates for {}...", url);
+            if let Err(e) = fetch_origin(&repo) {
+                eprintln!("Error: failed to fetch updates for {}: {}", url, e);
+                exit(1);
+            }
+        } else {
+            eprintln!("Using cached copy of {}", url);
+        }
+        repo
+    };
+
+    let mut version = None;
+    let commit = match (&pin, reference) {
+        (Pin::Commit(pinned), _) => Oid::from_str(pinned).and_then(|oid| {
+            ensure_commit(&repo, oid, network)?;
+            Ok(oid)
+        }),
+        (_, GitRef::Version(req)) => {
+            let want = match pin {
+                Pin::Version(want) => Some(want),
+                _ => None,
+            };
+            resolve_version(&repo, req, want).map(|(oid, v)| {
+                version = Some(v);
+                oid
+            })
+        }
+        _ => resolve_ref(&repo, reference, network),
+    }
+    .and_then(|oid| checkout_commit(&repo, oid).map(|_| oid));
 
-            match super::fs_copy::copy_dir_recursive(&cache_path, &dst) {
-                Ok(_) => {}
-                Err(e) => eprintln!("Failed to copy from cache to destination: {}", e),
+    let available = match reference {
+        GitRef::Version(_) => version_tags(&repo)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(v, _)| v)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let commit = match commit {
+        Ok(commit) => commit,
+        Err(e) => {
+            match (&pin, reference) {
+                _ if e.message() == NOT_CACHED => eprintln!(
+                    "Error: the commit '{}' needs from {} is {}\nRun `seastar fetch` while online to download it.",
+                    dep.name, url, NOT_CACHED
+                ),
+                (Pin::Commit(pinned), _) => eprintln!(
+                    "Error: commit {} of {} (pinned in Seastar.lock) is not available: {}",
+                    pinned, url, e
+                ),
+                (_, GitRef::Version(req)) => eprintln!(
+                    "Error: no tag of {} matches version {} (dependency '{}')\nAvailable versions: {}",
+                    url,
+                    req,
+                    dep.name,
+                    list_versions(&available)
+                ),
+                (_, GitRef::Branch(name)) => eprintln!(
+                    "Error: branch '{}' does not exist in {} (dependency '{}')",
+                    name, url, dep.name
+                ),
+                (_, GitRef::Tag(name)) => eprintln!(
+                    "Error: tag '{}' does not exist in {} (dependency '{}')",
+                    name, url, dep.name
+                ),
+                (_, GitRef::Rev(rev)) => eprintln!(
+                    "Error: revision '{}' does not exist in {} (dependency '{}')",
+                    rev, url, dep.name
+                ),
+                (_, GitRef::DefaultBranch) => {
+                    eprintln!("Error: failed to check out {}: {}", url, e)
+                }
             }
+            exit(1);
         }
-        DepSource::Path(path) => {
-            let src = Path::new(path);
-            let dst = Path::new(dep_dir).join(&dep.name);
-            match super::fs_copy::copy_dir_recursive(src, &dst) {
-                Ok(_) => {}
-                Err(e) => eprintln!("Failed to copy {}: {}", path, e),
+    };
+
+    copy_to_dep_dir(&cache_path, dst);
+    Fetched {
+        commit: Some(commit.to_string()),
+        version,
+        available,
+    }
+}
+
+/// Looks a package up in the registry index, fetches the version that
+/// matches `req` (or `pin`) from wherever the index says it lives, and
+/// checks its contents against the index checksum.
+fn fetch_registry(
+    dep: &Dep,
+    index: &str,
+    req: &VersionReq,
+    pin: Pin,
+    network: Network,
+    dst: &Path,
+) -> Fetched {
+    if index.is_empty() {
+        eprintln!(
+            "Error: dependency '{}' comes from the registry, but none is configured\nSet `[registry] index = \"...\"` in ~/.seastar/config.toml.",
+            dep.name
+        );
+        exit(1);
+    }
+    let registry = Registry::open(index, network);
+    let Some(entry) = registry.entry(&dep.name) else {
+        eprintln!(
+            "Error: package '{}' is not in the registry at {}",
+            dep.name, registry.location
+        );
+        exit(1);
+    };
+    let available: Vec<Version> = entry.versions.iter().map(|v| v.version.clone()).collect();
+
+    let want = match pin {
+        Pin::Version(want) => Some(want),
+        _ => None,
+    };
+    let Some(chosen) = entry
+        .versions
+        .iter()
+        .filter(|v| match want {
+            Some(want) => &v.version == want,
+            None => req.matches(&v.version),
+        })
+        .max_by(|a, b| a.version.cmp(&b.version))
+    else {
+        eprintln!(
+            "Error: no version of '{}' in the registry matches {}\nAvailable versions: {}",
+            dep.name,
+            want.map(|v| format!("={}", v))
+                .unwrap_or_else(|| req.to_string()),
+            list_versions(&available)
+        );
+        exit(1);
+    };
+
+    let commit = match &chosen.source {
+        IndexSource::Git { git, rev } => {
+            fetch_git(
+                dep,
+                git,
+                &GitRef::Rev(rev.clone()),
+                Pin::Latest,
+                network,
+                dst,
+            )
+            .commit
+        }
+        IndexSource::Path { path } => {
+            copy_to_dep_dir(&registry.root.join(path), dst);
+            None
+        }
+        IndexSource::Archive { archive } => {
+            let archive = registry.root.join(archive);
+            if dst.exists() {
+                let _ = std::fs::remove_dir_all(dst);
+            }
+            if let Err(e) = package::unpack(&archive, dst) {
+                eprintln!("Error: failed to unpack {}: {}", archive.display(), e);
+                exit(1);
             }
+            None
         }
+    };
+
+    let checksum = lockfile::checksum_dir(dst);
+    if checksum != chosen.checksum {
+        eprintln!(
+            "Error: {} {} doesn't match the checksum in the registry index\n  index:   {}\n  fetched: {}",
+            dep.name, chosen.version, chosen.checksum, checksum
+        );
+        exit(1);
+    }
+
+    Fetched {
+        commit,
+        version: Some(chosen.version.clone()),
+        available,
     }
 }
 
+/// Versions in ascending order, for error messages.
+fn list_versions(versions: &[Version]) -> String {
+    if versions.is_empty() {
+        return "none".to_string();
+    }
+    let mut versions = versions.to_vec();
+    versions.sort();
+    let versions: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+    versions.join(", ")
+}
+
 impl DepGraph {
+    /// Marks everything reachable from the root's normal dependencies as
+    /// `Normal`; what's left is only there for tests and examples.
+    fn assign_kinds(&mut self, normal: &[String]) {
+        for node in self.nodes.values_mut() {
+            node.kind = DepKind::Dev;
+        }
+        let mut pending = normal.to_vec();
+        while let Some(name) = pending.pop() {
+            if let Some(node) = self.nodes.get_mut(&name)
+                && node.kind == DepKind::Dev
+            {
+                node.kind = DepKind::Normal;
+                pending.extend(node.dependencies.iter().cloned());
+            }
+        }
+    }
+
+    fn to_lockfile(&self) -> Lockfile {
+        Lockfile::new(
+            self.nodes
+                .values()
+                .map(|node| LockedPackage {
+                    name: node.dep.name.clone(),
+                    source: node.dep.source.lock_source(),
+                    version: node.version.as_ref().map(|v| v.to_string()),
+                    commit: node.commit.clone(),
+                    checksum: node.checksum.clone(),
+                    dependencies: node.dependencies.clone(),
+                })
+                .collect(),
+        )
+    }
+
     pub fn topological_order(&self) -> Vec<&DepNode> {
         let mut order = Vec::new();
         let mut visited = HashSet::new();
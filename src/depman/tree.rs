@@ -0,0 +1,244 @@
+// `seastar tree`: the resolved dependency graph as an indented tree, an
+// inverted tree (who pulls a package in), or JSON for other tools.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use super::resolver::{DepGraph, DepKind, DepNode, DepSource, GitRef};
+use crate::io::Config;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum TreeFormat {
+    Text,
+    Json,
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+/// Where a node came from: `url@ref (commit)` for git, the path for path
+/// dependencies and the index for registry ones.
+fn describe_source(node: &DepNode) -> String {
+    let commit = node.commit.as_deref().map(short).unwrap_or("?");
+    match &node.dep.source {
+        DepSource::Git { repo, reference } => match reference {
+            GitRef::Branch(name) | GitRef::Tag(name) => {
+                format!("{}@{} ({})", repo, name, commit)
+            }
+            _ => format!("{}@{}", repo, commit),
+        },
+        DepSource::Path(path) => path.clone(),
+        DepSource::Registry { index, .. } => format!("registry {}", index),
+    }
+}
+
+fn version_of(node: &DepNode) -> Option<String> {
+    node.version
+        .as_ref()
+        .or(node.config.package.version.as_ref())
+        .map(|v| v.to_string())
+}
+
+/// `name vX (source)`, plus `[patched]` when `[patch]` or `[replace]`
+/// swapped the source and `[dev]` when only tests and examples use it.
+fn label(node: &DepNode) -> String {
+    let mut label = match version_of(node) {
+        Some(version) => format!("{} v{} ({})", node.dep.name, version, describe_source(node)),
+        None => format!("{} ({})", node.dep.name, describe_source(node)),
+    };
+    if node.patched_from.is_some() {
+        label.push_str(" [patched]");
+    }
+    if node.kind == DepKind::Dev {
+        label.push_str(" [dev]");
+    }
+    label
+}
+
+fn root_label(config: &Config) -> String {
+    match &config.package.version {
+        Some(version) => format!("{} v{}", config.package.project_name, version),
+        None => config.package.project_name.clone(),
+    }
+}
+
+/// Who pulls `name` in: the packages that list it, plus the root if it's
+/// a direct dependency. `root` is the root's label.
+fn parents(graph: &DepGraph, root_deps: &[String], name: &str, root: &str) -> Vec<String> {
+    let mut parents: Vec<String> = graph
+        .nodes
+        .values()
+        .filter(|n| n.dependencies.iter().any(|d| d == name))
+        .map(|n| n.dep.name.clone())
+        .collect();
+    parents.sort();
+    if root_deps.iter().any(|d| d == name) {
+        parents.push(root.to_string());
+    }
+    parents
+}
+
+/// Writes `children` under the current line, cargo-tree style. Subtrees
+/// that were already printed are shown once and then marked `(*)`.
+fn write_children(
+    out: &mut String,
+    prefix: &str,
+    children: &[String],
+    label_of: &dyn Fn(&str) -> String,
+    children_of: &dyn Fn(&str) -> Vec<String>,
+    printed: &mut HashSet<String>,
+) {
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        let grandchildren = children_of(child);
+        if !printed.insert(child.clone()) && !grandchildren.is_empty() {
+            out.push_str(&format!("{}{}{} (*)\n", prefix, branch, label_of(child)));
+            continue;
+        }
+        out.push_str(&format!("{}{}{}\n", prefix, branch, label_of(child)));
+        write_children(
+            out,
+            &format!("{}{}", prefix, indent),
+            &grandchildren,
+            label_of,
+            children_of,
+            printed,
+        );
+    }
+}
+
+fn sorted(names: &[String]) -> Vec<String> {
+    let mut names = names.to_vec();
+    names.sort();
+    names
+}
+
+/// The whole graph, starting at the root package.
+pub fn render(config: &Config, graph: &DepGraph, root_deps: &[String]) -> String {
+    let mut out = format!("{}\n", root_label(config));
+    write_children(
+        &mut out,
+        "",
+        &sorted(root_deps),
+        &|name| graph.nodes.get(name).map(label).unwrap_or(name.to_string()),
+        &|name| {
+            graph
+                .nodes
+                .get(name)
+                .map(|n| sorted(&n.dependencies))
+                .unwrap_or_default()
+        },
+        &mut HashSet::new(),
+    );
+    out
+}
+
+/// Everything that depends on `name`, directly or not, down to the root.
+pub fn render_inverted(
+    config: &Config,
+    graph: &DepGraph,
+    root_deps: &[String],
+    name: &str,
+) -> Option<String> {
+    let node = graph.nodes.get(name)?;
+    let root = root_label(config);
+    let mut out = format!("{}\n", label(node));
+    write_children(
+        &mut out,
+        "",
+        &parents(graph, root_deps, name, &root),
+        &|name| graph.nodes.get(name).map(label).unwrap_or(name.to_string()),
+        &|name| {
+            if graph.nodes.contains_key(name) {
+                parents(graph, root_deps, name, &root)
+            } else {
+                Vec::new()
+            }
+        },
+        &mut HashSet::new(),
+    );
+    Some(out)
+}
+
+/// Packages that more than one package pulls in, each as an inverted tree.
+pub fn render_duplicates(config: &Config, graph: &DepGraph, root_deps: &[String]) -> String {
+    let root = root_label(config);
+    let mut names: Vec<&str> = graph
+        .nodes
+        .keys()
+        .map(String::as_str)
+        .filter(|name| parents(graph, root_deps, name, &root).len() > 1)
+        .collect();
+    names.sort();
+
+    names
+        .iter()
+        .filter_map(|name| render_inverted(config, graph, root_deps, name))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Serialize)]
+struct JsonTree<'a> {
+    name: &'a str,
+    version: Option<String>,
+    dependencies: Vec<String>,
+    packages: BTreeMap<&'a str, JsonPackage<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonPackage<'a> {
+    version: Option<String>,
+    source: String,
+    commit: Option<&'a str>,
+    checksum: &'a str,
+    dependencies: Vec<String>,
+    /// `normal`, or `dev` for packages only tests and examples use.
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    features: Vec<&'a str>,
+    /// The source the graph asked for, if this package was patched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    patched_from: Option<String>,
+}
+
+/// The graph as JSON: the root's direct dependencies plus every package
+/// keyed by name.
+pub fn render_json(config: &Config, graph: &DepGraph, root_deps: &[String]) -> String {
+    let tree = JsonTree {
+        name: &config.package.project_name,
+        version: config.package.version.as_ref().map(|v| v.to_string()),
+        dependencies: sorted(root_deps),
+        packages: graph
+            .nodes
+            .iter()
+            .map(|(name, node)| {
+                (
+                    name.as_str(),
+                    JsonPackage {
+                        version: version_of(node),
+                        source: node.dep.source.to_string(),
+                        commit: node.commit.as_deref(),
+                        checksum: &node.checksum,
+                        dependencies: sorted(&node.dependencies),
+                        kind: match node.kind {
+                            DepKind::Normal => "normal",
+                            DepKind::Dev => "dev",
+                        },
+                        features: node.features.enabled.iter().map(String::as_str).collect(),
+                        patched_from: node.patched_from.as_ref().map(|s| s.to_string()),
+                    },
+                )
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&tree).expect("Failed to serialize dependency tree")
+}
@@ -0,0 +1,113 @@
+// `seastar vendor` copies every fetched package into `vendor/` so the whole
+// graph can live in the project's own repository. `vendor/config.toml` maps
+// each source, as written in Seastar.lock, to its vendored directory; while
+// it exists, those sources are taken from `vendor/` instead of the package
+// cache.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use super::lockfile;
+use super::resolver::{DepGraph, DepSource};
+
+pub const VENDOR_DIR: &str = "vendor";
+const VENDOR_CONFIG: &str = "config.toml";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct VendorConfig {
+    #[serde(default)]
+    pub source: BTreeMap<String, VendoredSource>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VendoredSource {
+    /// Directory holding the package, relative to `vendor/`.
+    pub directory: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<semver::Version>,
+}
+
+impl VendoredSource {
+    pub fn path(&self) -> PathBuf {
+        Path::new(VENDOR_DIR).join(&self.directory)
+    }
+}
+
+/// The redirects in `vendor/config.toml`, if the project is vendored.
+pub fn load() -> Option<VendorConfig> {
+    let path = Path::new(VENDOR_DIR).join(VENDOR_CONFIG);
+    let data = fs::read_to_string(&path).ok()?;
+    match toml::from_str::<VendorConfig>(&data) {
+        Ok(config) => Some(VendorConfig {
+            source: config
+                .source
+                .into_iter()
+                .map(|(source, vendored)| (lockfile::migrate_source(&source), vendored))
+                .collect(),
+        }),
+        Err(e) => {
+            eprintln!("Error: failed to parse {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+/// The registry index the vendored registry packages came from, so a
+/// vendored build needs no `[registry]` of its own.
+pub fn registry_index() -> Option<String> {
+    load()?
+        .source
+        .keys()
+        .find_map(|source| source.strip_prefix("registry+"))
+        .map(str::to_string)
+}
+
+/// Replaces `vendor/` with a copy of every package in `graph` (fetched into
+/// `dep_dir`) and writes the config that redirects their sources to it.
+/// Path dependencies are already on disk and are left where they are.
+pub fn vendor(graph: &DepGraph, dep_dir: &str) -> VendorConfig {
+    let vendor_dir = Path::new(VENDOR_DIR);
+    if vendor_dir.exists()
+        && let Err(e) = fs::remove_dir_all(vendor_dir)
+    {
+        eprintln!("Error: failed to remove {}: {}", vendor_dir.display(), e);
+        exit(1);
+    }
+
+    let mut config = VendorConfig::default();
+    for node in graph.nodes.values() {
+        if matches!(node.dep.source, DepSource::Path(_)) {
+            continue;
+        }
+        let vendored = VendoredSource {
+            directory: node.dep.name.clone(),
+            commit: node.commit.clone(),
+            version: node.version.clone(),
+        };
+        let src = Path::new(dep_dir).join(&node.dep.name);
+        if let Err(e) = super::fs_copy::copy_dir_recursive(&src, &vendored.path()) {
+            eprintln!("Error: failed to vendor {}: {}", node.dep.name, e);
+            exit(1);
+        }
+        config
+            .source
+            .insert(node.dep.source.lock_source(), vendored);
+    }
+
+    let path = vendor_dir.join(VENDOR_CONFIG);
+    let data = toml::to_string(&config).expect("Failed to serialize vendor config");
+    let data = format!(
+        "# Written by `seastar vendor`. While this file exists, these sources\n# are built from vendor/ instead of being fetched.\n\n{}",
+        data
+    );
+    if let Err(e) = fs::create_dir_all(vendor_dir).and_then(|_| fs::write(&path, data)) {
+        eprintln!("Error: failed to write {}: {}", path.display(), e);
+        exit(1);
+    }
+    config
+}
@@ -1,7 +1,6 @@
 use serde::Deserialize;
 use std::{collections::HashMap, fs, path::PathBuf};
 
-
 #[derive(Debug, Deserialize)]
 pub struct Config {
     #[serde(default)]
@@ -11,6 +10,44 @@ pub struct Config {
 
     #[serde(default)]
     pub dependencies: HashMap<String, DepSpec>,
+
+    /// Only built and linked for tests and examples.
+    #[serde(default, rename = "dev-dependencies")]
+    pub dev_dependencies: HashMap<String, DepSpec>,
+
+    /// Not supported yet: there are no build scripts to hand them to. The
+    /// root package is rejected if it lists any; a dependency's are ignored
+    /// with a warning.
+    #[serde(default, rename = "build-dependencies")]
+    pub build_dependencies: HashMap<String, DepSpec>,
+
+    /// `[features]`: what each feature turns on. See `depman::features`.
+    #[serde(default)]
+    pub features: HashMap<String, Vec<String>>,
+
+    /// `[patch."<url>"]`: packages to take from elsewhere whenever the graph
+    /// asks for them from `<url>`. Only the root package's patches apply.
+    #[serde(default)]
+    pub patch: HashMap<String, HashMap<String, DepSpec>>,
+
+    /// `[replace]`: packages to take from elsewhere whatever their source.
+    #[serde(default)]
+    pub replace: HashMap<String, DepSpec>,
+
+    /// `[target.<triple>]` and `[target.'cfg(...)']`: dependencies and
+    /// options for some platforms only. See `platform`.
+    #[serde(default)]
+    pub target: HashMap<String, TargetConfig>,
+
+    #[serde(default)]
+    pub profile: HashMap<String, ProfileConfig>,
+
+    #[serde(default)]
+    pub lib: Option<LibTarget>,
+    #[serde(default)]
+    pub bin: Vec<BinTarget>,
+    #[serde(default)]
+    pub example: Vec<BinTarget>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -18,6 +55,15 @@ pub struct Package {
     pub project_name: String,
     pub compiler: String,
 
+    /// A semver version, e.g. `1.2.3`. Shared libraries use it for their
+    /// soname (`libfoo.so.1`).
+    #[serde(default)]
+    pub version: Option<semver::Version>,
+
+    /// Shown by `seastar search` once the package is published.
+    #[serde(default)]
+    pub description: Option<String>,
+
     #[serde(default)]
     pub is_lib: bool,
 
@@ -38,14 +84,160 @@ pub struct Options {
     pub cpp_link_flags: Option<String>,
 }
 
+/// One `[target.*]` table, merged into the package when it matches the
+/// platform being built for.
+#[derive(Debug, Deserialize, Default)]
+pub struct TargetConfig {
+    #[serde(default)]
+    pub dependencies: HashMap<String, DepSpec>,
+    #[serde(default, rename = "dev-dependencies")]
+    pub dev_dependencies: HashMap<String, DepSpec>,
+    #[serde(default)]
+    pub options: Options,
+}
+
+/// The `[lib]` table. Every file in `src/` that isn't the entry point of a
+/// `[[bin]]` goes into the library.
+#[derive(Debug, Deserialize, Default)]
+pub struct LibTarget {
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub crate_type: CrateType,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CrateType {
+    #[default]
+    Static,
+    Shared,
+    Both,
+}
+
+impl CrateType {
+    pub fn is_static(&self) -> bool {
+        matches!(self, CrateType::Static | CrateType::Both)
+    }
+
+    pub fn is_shared(&self) -> bool {
+        matches!(self, CrateType::Shared | CrateType::Both)
+    }
+}
+
+/// A `[[bin]]` or `[[example]]` table; `path` is the source file with `main`.
+#[derive(Debug, Deserialize)]
+pub struct BinTarget {
+    pub name: String,
+    pub path: String,
+}
+
+/// A `[profile.<name>]` table. Anything left out comes from the built-in
+/// profile of the same name, or from the profile named by `inherits`.
+#[derive(Debug, Deserialize, Default)]
+pub struct ProfileConfig {
+    pub inherits: Option<String>,
+    pub opt_level: Option<OptLevel>,
+    pub debug: Option<bool>,
+    pub pic: Option<bool>,
+    pub defines: Option<Vec<String>>,
+    pub c_flags: Option<String>,
+    pub cpp_flags: Option<String>,
+    pub link_flags: Option<String>,
+}
+
+/// `opt_level = 2` and `opt_level = "s"` are both allowed.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum OptLevel {
+    Number(u8),
+    Name(String),
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum DepSpec {
+    /// A git URL, or a version requirement for a registry package.
     Simple(String),
     Detailed {
         git: Option<String>,
+        /// At most one of `branch`, `tag` and `rev` may be set.
+        branch: Option<String>,
         tag: Option<String>,
+        rev: Option<String>,
+        /// A semver requirement such as `^1.2`, matched against the
+        /// repository's tags. Can't be combined with `branch`, `tag` or `rev`.
+        /// Without `git`, the package comes from the registry.
+        version: Option<semver::VersionReq>,
         path: Option<String>,
+        /// Only used when a feature turns it on.
+        #[serde(default)]
+        optional: bool,
+        /// Features of the dependency to turn on.
+        #[serde(default)]
+        features: Vec<String>,
+        /// Whether the dependency's `default` feature is on.
+        #[serde(default = "default_true", rename = "default-features")]
+        default_features: bool,
+    },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl DepSpec {
+    pub fn is_optional(&self) -> bool {
+        matches!(self, DepSpec::Detailed { optional: true, .. })
+    }
+}
+
+/// Per-user settings from `~/.seastar/config.toml`. Every field is optional,
+/// so a missing file just means "use the defaults".
+#[derive(Debug, Deserialize, Default)]
+pub struct UserConfig {
+    #[serde(default)]
+    pub build: BuildConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub net: NetConfig,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct BuildConfig {
+    /// Number of compiler processes to run at once.
+    pub jobs: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct RegistryConfig {
+    /// Where the package index lives: a directory, a `file://` URL or a git
+    /// URL.
+    pub index: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct NetConfig {
+    /// Never touch the network; resolve everything from the package cache.
+    #[serde(default)]
+    pub offline: bool,
+}
+
+const USER_CONFIG_PATH: &str = "~/.seastar/config.toml";
+
+pub fn load_user_config() -> UserConfig {
+    let path = shellexpand::tilde(USER_CONFIG_PATH).to_string();
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(_) => return UserConfig::default(),
+    };
+    match toml::from_str(&data) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", USER_CONFIG_PATH, e);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -55,7 +247,7 @@ pub fn load_config<P: Into<PathBuf>>(path: P) -> Config {
         Err(_) => {
             println!("Failed to read config file!");
             std::process::exit(1);
-        },
+        }
     };
     toml::from_str(&data).expect("Failed to parse TOML")
 }
@@ -1,13 +1,20 @@
+use std::path::PathBuf;
 use std::process::exit;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use depman::tree::TreeFormat;
+use tester::report::ReportFormat;
 
 mod app;
 mod builder;
 mod depman;
 mod io;
 mod language;
+mod platform;
+mod profile;
 mod templater;
+mod tester;
 
 #[derive(Parser)]
 #[command(name = "seastar")]
@@ -15,18 +22,169 @@ mod templater;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    #[command(flatten)]
+    resolve: ResolveArgs,
+}
+
+#[derive(Args)]
+struct ResolveArgs {
+    /// Resolve dependencies only from the package cache, without network access
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Features to enable, separated by commas
+    #[arg(long, global = true, value_delimiter = ',')]
+    features: Vec<String>,
+
+    /// Don't enable the `default` feature
+    #[arg(long, global = true)]
+    no_default_features: bool,
+
+    /// Target triple to evaluate `[target]` tables against (default: the compiler's)
+    #[arg(long, global = true)]
+    target: Option<String>,
+}
+
+#[derive(Args)]
+struct ProfileArgs {
+    /// Build with the release profile
+    #[arg(long, conflicts_with = "profile")]
+    release: bool,
+
+    /// Build with the given profile (dev, release, or one from Seastar.toml)
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+impl ProfileArgs {
+    fn name(&self) -> &str {
+        profile::selected_name(self.release, self.profile.as_deref())
+    }
+
+    /// `None` when neither flag was given.
+    fn explicit_name(&self) -> Option<&str> {
+        (self.release || self.profile.is_some()).then(|| self.name())
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Build the binary/static library
-    Build,
+    /// Build the library and binaries of the package
+    Build {
+        /// Number of parallel compiler jobs (defaults to the number of CPUs)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Also build the [[example]] targets
+        #[arg(long, default_value_t = false)]
+        examples: bool,
+
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+
+    /// Build and run a binary
+    Run {
+        /// Number of parallel compiler jobs (defaults to the number of CPUs)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Name of the [[bin]] target to run
+        #[arg(long, conflicts_with = "example")]
+        bin: Option<String>,
+
+        /// Name of the [[example]] target to run
+        #[arg(long)]
+        example: Option<String>,
+
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+
+    /// Build and run the tests in tests/
+    Test {
+        /// Only run tests whose name contains this string
+        filter: Option<String>,
+
+        /// Number of parallel jobs for compiling and running tests
+        #[arg(short, long)]
+        jobs: Option<usize>,
 
-    /// Build and run the binary
-    Run,
+        /// Seconds a single test may run before it is killed
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Also produce a report in this format
+        #[arg(long, value_enum)]
+        format: Option<ReportFormat>,
+
+        /// File to write the report to (defaults to stdout)
+        #[arg(long, requires = "format")]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
 
     /// Clean compiled dependencies and object files
-    Clean,
+    Clean {
+        /// Only clean the output of this profile
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+
+    /// Write compile_commands.json for clangd and other tools
+    Compdb {
+        /// Also include commands for dependencies under deps/
+        #[arg(long, default_value_t = false)]
+        deps: bool,
+
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+
+    /// Re-resolve dependencies and update the pins in Seastar.lock
+    Update {
+        /// Only update these dependencies (default: all of them)
+        packages: Vec<String>,
+    },
+
+    /// Download every dependency into the package cache for offline use
+    Fetch,
+
+    /// Copy every dependency into vendor/ and build from there
+    Vendor,
+
+    /// Print the resolved dependency graph
+    Tree {
+        /// Show what depends on this package instead
+        #[arg(long, value_name = "DEP", conflicts_with = "duplicates")]
+        invert: Option<String>,
+
+        /// Only show packages pulled in by more than one package
+        #[arg(long, default_value_t = false)]
+        duplicates: bool,
+
+        #[arg(long, value_enum, default_value = "text")]
+        format: TreeFormat,
+    },
+
+    /// Build a distributable archive of this package in target/package
+    Package,
+
+    /// Package this library and add it to a registry
+    Publish {
+        /// Registry directory to publish to (default: the configured registry)
+        #[arg(long)]
+        registry: Option<String>,
+    },
+
+    /// Search the package registry
+    Search {
+        /// Text to look for in package names and descriptions
+        query: String,
+    },
 
     /// Create a new project with a template
     New {
@@ -58,17 +216,61 @@ enum Commands {
 fn main() {
     let cli = Cli::parse();
 
+    let resolve = &app::resolve_options(
+        cli.resolve.offline,
+        &cli.resolve.features,
+        cli.resolve.no_default_features,
+        cli.resolve.target.as_deref(),
+    );
     match &cli.command {
-        Some(Commands::Build) => app::build(),
-        Some(Commands::Run) => {
-            let config = io::load_config("Seastar.toml");
-            if config.package.is_lib {
-                eprintln!("Error: Cannot run a library package");
-                exit(1);
-            }
-            app::run()
+        Some(Commands::Build {
+            jobs,
+            examples,
+            profile,
+        }) => app::build(profile.name(), *jobs, *examples, resolve),
+        Some(Commands::Run {
+            jobs,
+            bin,
+            example,
+            profile,
+        }) => app::run(
+            profile.name(),
+            *jobs,
+            bin.as_deref(),
+            example.as_deref(),
+            resolve,
+        ),
+        Some(Commands::Test {
+            filter,
+            jobs,
+            timeout,
+            format,
+            output,
+            profile,
+        }) => app::test(
+            profile.name(),
+            *jobs,
+            filter.as_deref(),
+            Duration::from_secs(*timeout),
+            *format,
+            output.as_deref(),
+            resolve,
+        ),
+        Some(Commands::Clean { profile }) => app::clean(profile.explicit_name()),
+        Some(Commands::Compdb { deps, profile }) => app::compdb(profile.name(), *deps, resolve),
+        Some(Commands::Update { packages }) => app::update(packages, resolve),
+        Some(Commands::Fetch) => app::fetch(resolve),
+        Some(Commands::Vendor) => app::vendor(resolve),
+        Some(Commands::Tree {
+            invert,
+            duplicates,
+            format,
+        }) => app::tree(invert.as_deref(), *duplicates, *format, resolve),
+        Some(Commands::Package) => {
+            app::package();
         }
-        Some(Commands::Clean) => app::clean(),
+        Some(Commands::Publish { registry }) => app::publish(registry.as_deref()),
+        Some(Commands::Search { query }) => app::search(query, resolve),
         Some(Commands::New {
             project_name,
             language,
@@ -77,11 +279,9 @@ fn main() {
             cpp_compiler,
             c_compiler,
         }) => {
-            use std::path::PathBuf;
-
             let (c_compiler, cpp_compiler) = match language.as_str() {
                 "c" => (
-                    if &compiler.as_ref().unwrap_or_else(|| &c_compiler) == &c_compiler {
+                    if compiler.as_ref().unwrap_or(c_compiler) == c_compiler {
                         cpp_compiler.clone()
                     } else {
                         eprintln!(
@@ -93,7 +293,7 @@ fn main() {
                 ),
                 "cpp" | "c++" => (
                     c_compiler.clone(),
-                    if &compiler.as_ref().unwrap_or_else(|| &cpp_compiler) == &cpp_compiler {
+                    if compiler.as_ref().unwrap_or(cpp_compiler) == cpp_compiler {
                         cpp_compiler.clone()
                     } else {
                         eprintln!(
@@ -119,7 +319,9 @@ fn main() {
             );
         }
         None => {
-            println!("Commands: build, run, clean, new");
+            println!(
+                "Commands: build, run, test, clean, compdb, update, fetch, vendor, tree, package, publish, search, new"
+            );
         }
     }
 }
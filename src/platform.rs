@@ -0,0 +1,521 @@
+// The platform a build is for, and the `[target.<triple>]` and
+// `[target.'cfg(...)']` tables in Seastar.toml that only apply to some
+// platforms. Matching tables are merged into the package's dependencies
+// and `[options]` before anything else looks at them.
+
+use std::process::{Command, exit};
+
+use crate::io::Config;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Platform {
+    pub triple: String,
+    pub arch: String,
+    pub vendor: String,
+    pub os: String,
+    pub env: String,
+}
+
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "i386" | "i486" | "i586" | "i686" => "x86",
+        _ => arch,
+    }
+}
+
+fn os_of(part: &str) -> Option<&'static str> {
+    Some(match part {
+        "linux" => "linux",
+        "windows" | "mingw32" | "cygwin" => "windows",
+        _ if part.starts_with("darwin") || part.starts_with("macos") => "macos",
+        _ if part.starts_with("freebsd") => "freebsd",
+        _ if part.starts_with("netbsd") => "netbsd",
+        _ if part.starts_with("openbsd") => "openbsd",
+        "none" | "elf" => "none",
+        _ => return None,
+    })
+}
+
+impl Platform {
+    /// Reads a GNU-style triple such as `x86_64-linux-gnu`,
+    /// `aarch64-unknown-linux-musl` or `x86_64-w64-mingw32`.
+    pub fn from_triple(triple: &str) -> Platform {
+        let parts: Vec<&str> = triple.split('-').collect();
+        let rest = &parts[1..];
+        let os_index = rest.iter().position(|part| os_of(part).is_some());
+
+        let mut os = os_index
+            .and_then(|i| os_of(rest[i]))
+            .unwrap_or("unknown")
+            .to_string();
+        let vendor = match os_index {
+            Some(0) => "unknown",
+            _ => rest.first().copied().unwrap_or("unknown"),
+        };
+        let mut env = match os_index.and_then(|i| rest.get(i + 1)) {
+            Some(env) if env.starts_with("gnu") => "gnu".to_string(),
+            Some(env) if env.starts_with("musl") => "musl".to_string(),
+            Some(env) if env.starts_with("android") => {
+                os = "android".to_string();
+                String::new()
+            }
+            Some(env) => env.to_string(),
+            None => String::new(),
+        };
+        if os_index.is_some_and(|i| rest[i] == "mingw32") {
+            env = "gnu".to_string();
+        }
+
+        Platform {
+            triple: triple.to_string(),
+            arch: normalize_arch(parts[0]).to_string(),
+            vendor: vendor.to_string(),
+            os,
+            env,
+        }
+    }
+
+    fn family(&self) -> &str {
+        match self.os.as_str() {
+            "windows" => "windows",
+            "none" | "unknown" => "",
+            _ => "unix",
+        }
+    }
+
+    fn cfg_value(&self, key: &str) -> Option<&str> {
+        match key {
+            "target_arch" => Some(&self.arch),
+            "target_vendor" => Some(&self.vendor),
+            "target_os" => Some(&self.os),
+            "target_env" => Some(&self.env),
+            "target_family" => Some(self.family()),
+            _ => None,
+        }
+    }
+
+    /// Whether two triples name the same target, however they're spelled:
+    /// `x86_64-linux-gnu` is `x86_64-unknown-linux-gnu` and
+    /// `x86_64-pc-linux-gnu`, but not `x86_64-unknown-linux-musl`.
+    fn same_target(&self, other: &Platform) -> bool {
+        let any_vendor = |vendor: &str| matches!(vendor, "unknown" | "pc");
+        self.arch == other.arch
+            && self.os == other.os
+            && self.env == other.env
+            && (self.vendor == other.vendor
+                || any_vendor(&self.vendor)
+                || any_vendor(&other.vendor))
+    }
+
+    /// Whether a `[target.<key>]` table applies: `cfg(...)` keys are
+    /// evaluated, anything else has to name the same target as the triple.
+    pub fn matches(&self, key: &str) -> Result<bool, String> {
+        let Some(expr) = key.trim().strip_prefix("cfg(") else {
+            return Ok(self.same_target(&Platform::from_triple(key.trim())));
+        };
+        let Some(expr) = expr.strip_suffix(')') else {
+            return Err("missing the closing ')'".to_string());
+        };
+        let tokens = tokenize(expr)?;
+        let mut parser = CfgParser {
+            tokens: &tokens,
+            pos: 0,
+            platform: self,
+        };
+        let result = parser.expr()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(result),
+            token => Err(format!("unexpected {}", describe(token))),
+        }
+    }
+}
+
+/// `--target` when given, otherwise whatever `compiler -dumpmachine` says,
+/// and failing that the machine Seastar runs on.
+pub fn detect(explicit: Option<&str>, compiler: &str) -> Platform {
+    if let Some(triple) = explicit {
+        return Platform::from_triple(triple);
+    }
+    let dumped = Command::new(compiler)
+        .arg("-dumpmachine")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|triple| !triple.is_empty());
+    match dumped {
+        Some(triple) => Platform::from_triple(&triple),
+        None => Platform::from_triple(&format!(
+            "{}-{}",
+            std::env::consts::ARCH,
+            std::env::consts::OS
+        )),
+    }
+}
+
+fn append_flags(flags: &mut String, more: &str) {
+    if more.trim().is_empty() {
+        return;
+    }
+    if !flags.trim().is_empty() {
+        flags.push(' ');
+    }
+    flags.push_str(more.trim());
+}
+
+fn append_optional_flags(flags: &mut Option<String>, more: &Option<String>) {
+    if let Some(more) = more {
+        append_flags(flags.get_or_insert_with(String::new), more);
+    }
+}
+
+fn matching_keys(config: &Config, platform: &Platform) -> Vec<String> {
+    let mut keys: Vec<&String> = config.target.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .filter(|key| {
+            platform.matches(key).unwrap_or_else(|e| {
+                eprintln!(
+                    "Error: can't read [target.'{}'] in '{}': {}",
+                    key, config.package.project_name, e
+                );
+                exit(1);
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Merges every `[target]` table that applies to `platform` into the
+/// package's dependencies and options. Flags are appended; a dependency
+/// listed again replaces the general one.
+pub fn apply(config: &mut Config, platform: &Platform) {
+    for key in matching_keys(config, platform) {
+        let table = &config.target[&key];
+        config.dependencies.extend(table.dependencies.clone());
+        config
+            .dev_dependencies
+            .extend(table.dev_dependencies.clone());
+
+        let options = &mut config.options;
+        append_flags(&mut options.c_flags, &table.options.c_flags);
+        append_flags(&mut options.link_flags, &table.options.link_flags);
+        append_optional_flags(&mut options.cpp_flags, &table.options.cpp_flags);
+        append_optional_flags(&mut options.cpp_link_flags, &table.options.cpp_link_flags);
+    }
+}
+
+/// Dependencies that only tables for other platforms mention.
+pub fn inactive_deps(config: &Config, platform: &Platform) -> Vec<String> {
+    let active = matching_keys(config, platform);
+    let mut names: Vec<String> = config
+        .target
+        .iter()
+        .filter(|(key, _)| !active.contains(key))
+        .flat_map(|(_, table)| {
+            table
+                .dependencies
+                .keys()
+                .chain(table.dev_dependencies.keys())
+        })
+        .filter(|name| {
+            !config.dependencies.contains_key(*name) && !config.dev_dependencies.contains_key(*name)
+        })
+        .cloned()
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Adds the dependencies that only other platforms' tables list, keeping
+/// the normal/dev split, for commands like `seastar vendor` that have to
+/// cover every target. Where two tables list the same one, the first key in
+/// sorted order wins.
+pub fn include_inactive(config: &mut Config, platform: &Platform) {
+    let inactive = inactive_deps(config, platform);
+    let mut keys: Vec<&String> = config.target.keys().collect();
+    keys.sort();
+    for key in keys {
+        let table = &config.target[key];
+        for (specs, into) in [
+            (&table.dependencies, &mut config.dependencies),
+            (&table.dev_dependencies, &mut config.dev_dependencies),
+        ] {
+            for (name, spec) in specs.iter().filter(|(name, _)| inactive.contains(name)) {
+                into.entry(name.clone()).or_insert_with(|| spec.clone());
+            }
+        }
+    }
+}
+
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Eq,
+}
+
+/// How an error message refers to `token`.
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        Some(Token::Ident(name)) => format!("'{}'", name),
+        Some(Token::Str(value)) => format!("\"{}\"", value),
+        Some(Token::LParen) => "'('".to_string(),
+        Some(Token::RParen) => "')'".to_string(),
+        Some(Token::Comma) => "','".to_string(),
+        Some(Token::Eq) => "'='".to_string(),
+        None => "the end".to_string(),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' | '=' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ',' => Token::Comma,
+                    _ => Token::Eq,
+                });
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            _ if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            _ => return Err(format!("unexpected character '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Evaluates `cfg(...)` contents: `all(...)`, `any(...)`, `not(...)`,
+/// `key = "value"` and the bare names `unix` and `windows`. Anything else is
+/// an error, so a typo can't quietly turn a table off.
+struct CfgParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    platform: &'a Platform,
+}
+
+impl CfgParser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expr(&mut self) -> Result<bool, String> {
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name.clone(),
+            other => return Err(format!("expected a name, found {}", describe(other))),
+        };
+        match self.peek() {
+            Some(Token::Eq) => {
+                self.pos += 1;
+                let value = match self.next() {
+                    Some(Token::Str(value)) => value.clone(),
+                    other => return Err(format!("expected a string, found {}", describe(other))),
+                };
+                match self.platform.cfg_value(&name) {
+                    Some(actual) => Ok(actual == value),
+                    None => Err(format!("unknown cfg key '{}'", name)),
+                }
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                while !matches!(self.peek(), Some(Token::RParen)) {
+                    values.push(self.expr()?);
+                    match self.peek() {
+                        Some(Token::Comma) => self.pos += 1,
+                        Some(Token::RParen) => {}
+                        other => {
+                            return Err(format!("expected ',' or ')', found {}", describe(other)));
+                        }
+                    }
+                }
+                self.pos += 1;
+                match name.as_str() {
+                    "all" => Ok(values.iter().all(|v| *v)),
+                    "any" => Ok(values.iter().any(|v| *v)),
+                    "not" if values.len() == 1 => Ok(!values[0]),
+                    "not" => Err("not() takes exactly one condition".to_string()),
+                    _ => Err(format!("unknown predicate '{}'", name)),
+                }
+            }
+            _ if matches!(name.as_str(), "unix" | "windows") => Ok(self.platform.family() == name),
+            _ => Err(format!("unknown cfg name '{}'", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linux() -> Platform {
+        Platform::from_triple("x86_64-linux-gnu")
+    }
+
+    #[test]
+    fn reads_triples() {
+        let p = Platform::from_triple("aarch64-unknown-linux-musl");
+        assert_eq!(
+            (
+                p.arch.as_str(),
+                p.vendor.as_str(),
+                p.os.as_str(),
+                p.env.as_str()
+            ),
+            ("aarch64", "unknown", "linux", "musl")
+        );
+        let p = Platform::from_triple("x86_64-w64-mingw32");
+        assert_eq!(
+            (p.vendor.as_str(), p.os.as_str(), p.env.as_str()),
+            ("w64", "windows", "gnu")
+        );
+        let p = Platform::from_triple("arm64-apple-darwin23.1.0");
+        assert_eq!((p.arch.as_str(), p.os.as_str()), ("aarch64", "macos"));
+        let p = Platform::from_triple("armv7a-linux-androideabi");
+        assert_eq!((p.os.as_str(), p.env.as_str()), ("android", ""));
+    }
+
+    #[test]
+    fn triple_keys_ignore_the_vendor_spelling() {
+        let gcc = linux();
+        for key in [
+            "x86_64-linux-gnu",
+            "x86_64-unknown-linux-gnu",
+            "x86_64-pc-linux-gnu",
+            " x86_64-unknown-linux-gnu ",
+        ] {
+            assert_eq!(gcc.matches(key), Ok(true), "{}", key);
+        }
+        for key in [
+            "x86_64-unknown-linux-musl",
+            "aarch64-unknown-linux-gnu",
+            "x86_64-pc-windows-gnu",
+        ] {
+            assert_eq!(gcc.matches(key), Ok(false), "{}", key);
+        }
+
+        let apple = Platform::from_triple("aarch64-apple-darwin");
+        assert_eq!(apple.matches("aarch64-apple-macos"), Ok(true));
+        assert_eq!(apple.matches("aarch64-unknown-darwin"), Ok(true));
+        let mingw = Platform::from_triple("x86_64-w64-mingw32");
+        assert_eq!(mingw.matches("x86_64-pc-windows-gnu"), Ok(true));
+    }
+
+    #[test]
+    fn cfg_key_value() {
+        let p = linux();
+        assert_eq!(p.matches(r#"cfg(target_os = "linux")"#), Ok(true));
+        assert_eq!(p.matches(r#"cfg(target_os = "windows")"#), Ok(false));
+        assert_eq!(p.matches(r#"cfg(target_arch="x86_64")"#), Ok(true));
+        assert_eq!(p.matches(r#"cfg(target_env = "gnu")"#), Ok(true));
+        assert_eq!(p.matches(r#"cfg(target_vendor = "unknown")"#), Ok(true));
+        assert_eq!(p.matches(r#"cfg(target_family = "unix")"#), Ok(true));
+    }
+
+    #[test]
+    fn cfg_names_and_predicates() {
+        let p = linux();
+        assert_eq!(p.matches("cfg(unix)"), Ok(true));
+        assert_eq!(p.matches("cfg(windows)"), Ok(false));
+        assert_eq!(p.matches("cfg(not(windows))"), Ok(true));
+        assert_eq!(
+            p.matches(r#"cfg(all(unix, target_arch = "x86_64", not(target_env = "musl")))"#),
+            Ok(true)
+        );
+        assert_eq!(
+            p.matches(r#"cfg(all(unix, target_arch = "aarch64"))"#),
+            Ok(false)
+        );
+        assert_eq!(
+            p.matches(r#"cfg(any(windows, target_os = "linux"))"#),
+            Ok(true)
+        );
+        assert_eq!(
+            p.matches("cfg(any(windows, target_os = \"macos\",))"),
+            Ok(false)
+        );
+        assert_eq!(p.matches("cfg(all())"), Ok(true));
+        assert_eq!(p.matches("cfg(any())"), Ok(false));
+
+        let windows = Platform::from_triple("x86_64-pc-windows-msvc");
+        assert_eq!(windows.matches("cfg(windows)"), Ok(true));
+        assert_eq!(windows.matches("cfg(unix)"), Ok(false));
+    }
+
+    #[test]
+    fn cfg_errors() {
+        let p = linux();
+        let err = |key: &str| p.matches(key).unwrap_err();
+        assert_eq!(err(r#"cfg(target_os = "linux""#), "missing the closing ')'");
+        assert_eq!(
+            err(r#"cfg(target_oss = "linux")"#),
+            "unknown cfg key 'target_oss'"
+        );
+        assert_eq!(err("cfg(linux)"), "unknown cfg name 'linux'");
+        assert_eq!(err("cfg(either(unix))"), "unknown predicate 'either'");
+        assert_eq!(
+            err("cfg(not(unix, windows))"),
+            "not() takes exactly one condition"
+        );
+        assert_eq!(err("cfg(not())"), "not() takes exactly one condition");
+        assert_eq!(err(r#"cfg(target_os = "linux)"#), "unterminated string");
+        assert_eq!(
+            err("cfg(target_os == \"linux\")"),
+            "expected a string, found '='"
+        );
+        assert_eq!(
+            err("cfg(target_os = linux)"),
+            "expected a string, found 'linux'"
+        );
+        assert_eq!(err("cfg(unix & windows)"), "unexpected character '&'");
+        assert_eq!(err("cfg()"), "expected a name, found the end");
+        assert_eq!(err(r#"cfg("linux")"#), "expected a name, found \"linux\"");
+        assert_eq!(
+            err("cfg(all(unix windows))"),
+            "expected ',' or ')', found 'windows'"
+        );
+        assert_eq!(err("cfg(all(unix,)"), "expected a name, found the end");
+        assert_eq!(err("cfg(unix, windows)"), "unexpected ','");
+        assert_eq!(err("cfg(unix))"), "unexpected ')'");
+    }
+}
@@ -0,0 +1,167 @@
+// Build profiles, in the spirit of Cargo's `[profile.*]` tables. `dev` and
+// `release` always exist; a `[profile.<name>]` table in Seastar.toml can
+// tweak them or define a new profile that `inherits` from another one.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::exit;
+
+use crate::io::{Config, OptLevel, ProfileConfig};
+
+pub const TARGET_DIR: &str = "target";
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub opt_level: String,
+    pub debug: bool,
+    /// Position-independent code; forced on when a shared library is built.
+    pub pic: bool,
+    pub defines: Vec<String>,
+    pub c_flags: String,
+    pub cpp_flags: String,
+    pub link_flags: String,
+}
+
+impl Profile {
+    fn builtin(name: &str) -> Option<Profile> {
+        match name {
+            "dev" => Some(Profile {
+                name: "dev".to_string(),
+                opt_level: "0".to_string(),
+                debug: true,
+                pic: false,
+                defines: Vec::new(),
+                c_flags: String::new(),
+                cpp_flags: String::new(),
+                link_flags: String::new(),
+            }),
+            "release" => Some(Profile {
+                name: "release".to_string(),
+                opt_level: "3".to_string(),
+                debug: false,
+                pic: false,
+                defines: vec!["NDEBUG".to_string()],
+                c_flags: String::new(),
+                cpp_flags: String::new(),
+                link_flags: String::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Like Cargo, the `dev` profile builds into `target/debug`.
+    pub fn target_dir(&self) -> PathBuf {
+        let dir_name = if self.name == "dev" {
+            "debug"
+        } else {
+            &self.name
+        };
+        PathBuf::from(TARGET_DIR).join(dir_name)
+    }
+
+    /// Optimisation, debug info and defines, which go in front of the
+    /// package's own flags.
+    fn codegen_flags(&self) -> String {
+        let mut flags = vec![format!("-O{}", self.opt_level)];
+        if self.debug {
+            flags.push("-g".to_string());
+        }
+        if self.pic {
+            flags.push("-fPIC".to_string());
+        }
+        for define in &self.defines {
+            flags.push(format!("-D{}", define));
+        }
+        flags.join(" ")
+    }
+
+    pub fn compile_flags(&self, package_flags: &str, is_cpp: bool) -> String {
+        let profile_flags = if is_cpp {
+            &self.cpp_flags
+        } else {
+            &self.c_flags
+        };
+        join_flags(&[&self.codegen_flags(), package_flags, profile_flags])
+    }
+
+    pub fn link_flags(&self, package_flags: &str) -> String {
+        join_flags(&[package_flags, &self.link_flags])
+    }
+
+    fn apply(&mut self, overrides: &ProfileConfig) {
+        if let Some(opt_level) = &overrides.opt_level {
+            self.opt_level = match opt_level {
+                OptLevel::Number(n) => n.to_string(),
+                OptLevel::Name(s) => s.clone(),
+            };
+        }
+        if let Some(debug) = overrides.debug {
+            self.debug = debug;
+        }
+        if let Some(pic) = overrides.pic {
+            self.pic = pic;
+        }
+        if let Some(defines) = &overrides.defines {
+            self.defines = defines.clone();
+        }
+        if let Some(c_flags) = &overrides.c_flags {
+            self.c_flags = c_flags.clone();
+        }
+        if let Some(cpp_flags) = &overrides.cpp_flags {
+            self.cpp_flags = cpp_flags.clone();
+        }
+        if let Some(link_flags) = &overrides.link_flags {
+            self.link_flags = link_flags.clone();
+        }
+    }
+}
+
+fn join_flags(parts: &[&str]) -> String {
+    parts
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| p.trim())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turns `--release`/`--profile <name>` into a profile name.
+pub fn selected_name(release: bool, profile: Option<&str>) -> &str {
+    match profile {
+        Some(name) => name,
+        None if release => "release",
+        None => "dev",
+    }
+}
+
+pub fn resolve(config: &Config, name: &str) -> Profile {
+    resolve_inner(config, name, &mut HashSet::new())
+}
+
+fn resolve_inner(config: &Config, name: &str, seen: &mut HashSet<String>) -> Profile {
+    if !seen.insert(name.to_string()) {
+        eprintln!("Error: profile '{}' inherits from itself", name);
+        exit(1);
+    }
+
+    let overrides = config.profile.get(name);
+    let mut profile = match (Profile::builtin(name), overrides) {
+        (Some(builtin), _) => builtin,
+        (None, Some(overrides)) => {
+            let parent = overrides.inherits.as_deref().unwrap_or("dev");
+            let mut profile = resolve_inner(config, parent, seen);
+            profile.name = name.to_string();
+            profile
+        }
+        (None, None) => {
+            eprintln!("Error: profile '{}' is not defined in Seastar.toml", name);
+            exit(1);
+        }
+    };
+
+    if let Some(overrides) = overrides {
+        profile.apply(overrides);
+    }
+    profile
+}
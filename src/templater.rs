@@ -41,11 +41,7 @@ pub fn template(
 
     eprintln!(
         "Initialized {} package '{}' in current working directory!",
-        if is_lib {
-            "library"
-        } else {
-            "binary"
-        },
+        if is_lib { "library" } else { "binary" },
         project_name
     );
 }
@@ -53,7 +49,7 @@ pub fn template(
 fn copy_template(
     language: &str,
     is_lib: bool,
-    copy_to: &PathBuf,
+    copy_to: &Path,
     compiler: &str,
     cpp_compiler: &str,
     project_name: &str,
@@ -116,7 +112,7 @@ fn clone_templates(repo: &str) {
         eprintln!("Templates not found, cloning into {}", TEMPLATE_PATH);
 
         let output = Command::new("git")
-            .args(&["clone", "--depth", "1", repo, &expanded_template_dir])
+            .args(["clone", "--depth", "1", repo, &expanded_template_dir])
             .output()
             .expect("Failed to execute git clone. Are you sure git is installed?");
 
@@ -0,0 +1,287 @@
+// Runs the test programs built from `tests/`. Programs that use the bundled
+// `seastar_test.h` harness are asked for their test list and each test runs
+// as its own process; any other program counts as a single test that passes
+// when it exits with status 0.
+
+pub mod report;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// One program in `tests/` after it has been built.
+pub struct TestProgram {
+    pub name: String,
+    pub path: PathBuf,
+    pub source: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    /// The test program the test belongs to.
+    pub suite: String,
+    pub status: TestStatus,
+    pub duration: Duration,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+struct TestCase {
+    name: String,
+    suite: String,
+    program: PathBuf,
+    /// Argument selecting a single harness test, if any.
+    arg: Option<String>,
+}
+
+fn uses_harness(source: &Path) -> bool {
+    fs::read_to_string(source).is_ok_and(|src| src.contains("seastar_test.h"))
+}
+
+/// The tests in `program`. A harness that can't list its tests counts as a
+/// single failed test, so a broken program never drops out of the run. The
+/// listing runs with the same environment and time limit as the tests.
+fn list_cases(
+    program: &TestProgram,
+    timeout: Duration,
+    envs: &[(OsString, OsString)],
+) -> Result<Vec<TestCase>, TestResult> {
+    if !uses_harness(&program.source) {
+        return Ok(vec![TestCase {
+            name: program.name.clone(),
+            suite: program.name.clone(),
+            program: program.path.clone(),
+            arg: None,
+        }]);
+    }
+
+    let listing = run_program(&program.path, Some("--list"), timeout, envs);
+    if listing.status != TestStatus::Passed {
+        let reason = match listing.exit {
+            Some(exit) => exit.to_string(),
+            None if listing.status == TestStatus::TimedOut => "timed out".to_string(),
+            None => "could not be started".to_string(),
+        };
+        return Err(TestResult {
+            name: program.name.clone(),
+            suite: program.name.clone(),
+            status: listing.status,
+            duration: listing.duration,
+            stdout: listing.stdout,
+            stderr: format!(
+                "{}failed to list tests in {}: {}",
+                listing.stderr,
+                program.path.display(),
+                reason
+            ),
+        });
+    }
+    Ok(listing
+        .stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|case| TestCase {
+            name: format!("{}::{}", program.name, case.trim()),
+            suite: program.name.clone(),
+            program: program.path.clone(),
+            arg: Some(case.trim().to_string()),
+        })
+        .collect())
+}
+
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
+
+/// One run of a test program.
+struct Run {
+    status: TestStatus,
+    /// How the program exited, if it ran to the end.
+    exit: Option<ExitStatus>,
+    duration: Duration,
+    stdout: String,
+    stderr: String,
+}
+
+/// Runs `program`, killing it once it takes longer than `timeout`.
+fn run_program(
+    program: &Path,
+    arg: Option<&str>,
+    timeout: Duration,
+    envs: &[(OsString, OsString)],
+) -> Run {
+    let start = Instant::now();
+    let mut cmd = Command::new(program);
+    cmd.args(arg)
+        .envs(envs.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            return Run {
+                status: TestStatus::Failed,
+                exit: None,
+                duration: start.elapsed(),
+                stdout: String::new(),
+                stderr: format!("failed to start {}: {}\n", program.display(), e),
+            };
+        }
+    };
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let (status, exit) = loop {
+        match child.try_wait() {
+            Ok(Some(exit)) if exit.success() => break (TestStatus::Passed, Some(exit)),
+            Ok(Some(exit)) => break (TestStatus::Failed, Some(exit)),
+            Err(_) => break (TestStatus::Failed, None),
+            Ok(None) if start.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break (TestStatus::TimedOut, None);
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(5)),
+        }
+    };
+
+    Run {
+        status,
+        exit,
+        duration: start.elapsed(),
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }
+}
+
+fn run_case(case: &TestCase, timeout: Duration, envs: &[(OsString, OsString)]) -> TestResult {
+    let run = run_program(&case.program, case.arg.as_deref(), timeout, envs);
+    TestResult {
+        name: case.name.clone(),
+        suite: case.suite.clone(),
+        status: run.status,
+        duration: run.duration,
+        stdout: run.stdout,
+        stderr: run.stderr,
+    }
+}
+
+/// Runs every test whose name contains `filter`, `jobs` at a time. Results
+/// come back sorted by name. Progress goes to stdout only when `progress`
+/// is set, so it can't get mixed into a report printed there.
+pub fn run(
+    programs: &[TestProgram],
+    filter: Option<&str>,
+    jobs: usize,
+    timeout: Duration,
+    envs: &[(OsString, OsString)],
+    progress: bool,
+) -> Vec<TestResult> {
+    let mut cases = Vec::new();
+    let mut unlisted = Vec::new();
+    for program in programs {
+        match list_cases(program, timeout, envs) {
+            Ok(listed) => cases.extend(
+                listed
+                    .into_iter()
+                    .filter(|case| filter.is_none_or(|f| case.name.contains(f))),
+            ),
+            Err(failed) => unlisted.push(failed),
+        }
+    }
+
+    if progress {
+        println!("running {} tests", cases.len() + unlisted.len());
+        for result in &unlisted {
+            println!(
+                "test {} ... {} (could not list its tests)",
+                result.name,
+                match result.status {
+                    TestStatus::TimedOut => "TIMED OUT",
+                    _ => "FAILED",
+                }
+            );
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(unlisted);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, cases.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(case) = cases.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let result = run_case(case, timeout, envs);
+                    if progress {
+                        println!(
+                            "test {} ... {}",
+                            result.name,
+                            match result.status {
+                                TestStatus::Passed => "ok",
+                                TestStatus::Failed => "FAILED",
+                                TestStatus::TimedOut => "TIMED OUT",
+                            }
+                        );
+                    }
+                    results.lock().unwrap().push(result);
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    results
+}
+
+/// Prints the output of failed tests and the totals.
+pub fn print_summary(results: &[TestResult], elapsed: Duration) {
+    let failures: Vec<&TestResult> = results
+        .iter()
+        .filter(|r| r.status != TestStatus::Passed)
+        .collect();
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for result in &failures {
+            println!("\n---- {} ----", result.name);
+            if result.status == TestStatus::TimedOut {
+                println!("timed out after {:.2}s", result.duration.as_secs_f64());
+            }
+            print!("{}", result.stdout);
+            print!("{}", result.stderr);
+        }
+        println!();
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    println!(
+        "test result: {}. {} passed; {} failed; {} timed out; finished in {:.2}s",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        count(TestStatus::Passed),
+        count(TestStatus::Failed),
+        count(TestStatus::TimedOut),
+        elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,194 @@
+// Machine-readable test reports for CI systems: JUnit XML, TAP version 13
+// and plain JSON. Every format carries each test's name, duration, status
+// and captured output.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{TestResult, TestStatus};
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ReportFormat {
+    Junit,
+    Tap,
+    Json,
+}
+
+pub fn render(format: ReportFormat, results: &[TestResult], elapsed: Duration) -> String {
+    match format {
+        ReportFormat::Junit => junit(results, elapsed),
+        ReportFormat::Tap => tap(results),
+        ReportFormat::Json => json(results, elapsed),
+    }
+}
+
+fn count(results: &[&TestResult], status: TestStatus) -> usize {
+    results.iter().filter(|r| r.status == status).count()
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // XML 1.0 can't represent most control characters at all.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// One `<testsuite>` per test program, one `<testcase>` per test. Timeouts
+/// are reported as `<error>`, failed tests as `<failure>`.
+fn junit(results: &[TestResult], elapsed: Duration) -> String {
+    let mut suites: BTreeMap<&str, Vec<&TestResult>> = BTreeMap::new();
+    for result in results {
+        suites.entry(&result.suite).or_default().push(result);
+    }
+    let all: Vec<&TestResult> = results.iter().collect();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"seastar\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        count(&all, TestStatus::Failed),
+        count(&all, TestStatus::TimedOut),
+        elapsed.as_secs_f64()
+    ));
+
+    for (suite, cases) in suites {
+        let suite_time: f64 = cases.iter().map(|r| r.duration.as_secs_f64()).sum();
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+            escape_xml(suite),
+            cases.len(),
+            count(&cases, TestStatus::Failed),
+            count(&cases, TestStatus::TimedOut),
+            suite_time
+        ));
+        for case in cases {
+            let case_name = case
+                .name
+                .strip_prefix(&format!("{}::", suite))
+                .unwrap_or(&case.name);
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n",
+                escape_xml(case_name),
+                escape_xml(suite),
+                case.duration.as_secs_f64()
+            ));
+            match case.status {
+                TestStatus::Passed => {}
+                TestStatus::Failed => xml.push_str("      <failure message=\"test failed\"/>\n"),
+                TestStatus::TimedOut => xml.push_str(&format!(
+                    "      <error message=\"timed out after {:.2}s\"/>\n",
+                    case.duration.as_secs_f64()
+                )),
+            }
+            xml.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                escape_xml(&case.stdout)
+            ));
+            xml.push_str(&format!(
+                "      <system-err>{}</system-err>\n",
+                escape_xml(&case.stderr)
+            ));
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn tap_block(out: &mut String, key: &str, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    out.push_str(&format!("  {}: |\n", key));
+    for line in text.lines() {
+        out.push_str(&format!("    {}\n", line));
+    }
+}
+
+/// TAP version 13, with a YAML block holding duration, status and output.
+fn tap(results: &[TestResult]) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", results.len());
+    for (i, result) in results.iter().enumerate() {
+        let ok = if result.status == TestStatus::Passed {
+            "ok"
+        } else {
+            "not ok"
+        };
+        out.push_str(&format!("{} {} - {}\n", ok, i + 1, result.name));
+        out.push_str("  ---\n");
+        out.push_str(&format!(
+            "  duration_ms: {:.3}\n",
+            result.duration.as_secs_f64() * 1000.0
+        ));
+        out.push_str(&format!("  status: {}\n", status_name(result.status)));
+        tap_block(&mut out, "stdout", &result.stdout);
+        tap_block(&mut out, "stderr", &result.stderr);
+        out.push_str("  ...\n");
+    }
+    out
+}
+
+fn status_name(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Passed => "passed",
+        TestStatus::Failed => "failed",
+        TestStatus::TimedOut => "timed_out",
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    passed: usize,
+    failed: usize,
+    timed_out: usize,
+    duration_secs: f64,
+    tests: Vec<JsonTest<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonTest<'a> {
+    name: &'a str,
+    suite: &'a str,
+    status: &'static str,
+    duration_secs: f64,
+    stdout: &'a str,
+    stderr: &'a str,
+}
+
+fn json(results: &[TestResult], elapsed: Duration) -> String {
+    let all: Vec<&TestResult> = results.iter().collect();
+    let report = JsonReport {
+        passed: count(&all, TestStatus::Passed),
+        failed: count(&all, TestStatus::Failed),
+        timed_out: count(&all, TestStatus::TimedOut),
+        duration_secs: elapsed.as_secs_f64(),
+        tests: results
+            .iter()
+            .map(|r| JsonTest {
+                name: &r.name,
+                suite: &r.suite,
+                status: status_name(r.status),
+                duration_secs: r.duration.as_secs_f64(),
+                stdout: &r.stdout,
+                stderr: &r.stderr,
+            })
+            .collect(),
+    };
+    let mut out = serde_json::to_string_pretty(&report).expect("Failed to serialize test report");
+    out.push('\n');
+    out
+}
@@ -0,0 +1,101 @@
+// Resolving the same manifest twice has to write the same Seastar.lock, no
+// matter which dependent happens to reach a shared package first.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .expect("failed to run git");
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+fn write_package(dir: &Path, name: &str, dependencies: &str) {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Seastar.toml"),
+        format!(
+            "[package]\nproject_name = \"{}\"\ncompiler = \"cc\"\n\n[options]\nc_flags = \"\"\nlink_flags = \"\"\n\n[dependencies]\n{}",
+            name, dependencies
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("src").join(format!("{}.c", name)), "int x;\n").unwrap();
+}
+
+fn resolve(project: &Path, home: &Path) -> String {
+    let _ = fs::remove_dir_all(project.join("deps"));
+    let output = Command::new(env!("CARGO_BIN_EXE_seastar"))
+        .arg("tree")
+        .current_dir(project)
+        .env("HOME", home)
+        .output()
+        .expect("failed to run seastar");
+    assert!(
+        output.status.success(),
+        "seastar tree failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::read_to_string(project.join("Seastar.lock")).unwrap()
+}
+
+#[test]
+fn lock_is_identical_across_resolutions() {
+    let root: PathBuf = std::env::temp_dir().join(format!("seastar-lock-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let home = root.join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let leaf = root.join("leaf");
+    write_package(&leaf, "leaf", "");
+    git(&leaf, &["init", "-q"]);
+    git(&leaf, &["add", "-A"]);
+    git(&leaf, &["commit", "-qm", "1.0.0"]);
+    git(&leaf, &["tag", "v1.0.0"]);
+    fs::write(leaf.join("src/leaf.c"), "int x = 1;\n").unwrap();
+    git(&leaf, &["commit", "-qam", "1.1.0"]);
+    git(&leaf, &["tag", "v1.1.0"]);
+
+    let leaf_url = format!("file://{}", leaf.display());
+    let mut root_deps = String::new();
+    for (name, req) in [("a", "^1.0"), ("b", "^1.1"), ("c", "^1.0"), ("d", "^1.1")] {
+        write_package(
+            &root.join(name),
+            name,
+            &format!(
+                "leaf = {{ git = \"{}\", version = \"{}\" }}\n",
+                leaf_url, req
+            ),
+        );
+        root_deps.push_str(&format!(
+            "{} = {{ path = \"{}\" }}\n",
+            name,
+            root.join(name).display()
+        ));
+    }
+    let project = root.join("project");
+    write_package(&project, "project", &root_deps);
+
+    let first = resolve(&project, &home);
+    assert!(first.contains("?versions"), "unexpected lock:\n{}", first);
+    for _ in 0..4 {
+        assert_eq!(resolve(&project, &home), first);
+    }
+    for _ in 0..4 {
+        fs::remove_file(project.join("Seastar.lock")).unwrap();
+        assert_eq!(resolve(&project, &home), first);
+    }
+
+    // A newer tag doesn't move the pin once it's locked.
+    fs::write(leaf.join("src/leaf.c"), "int x = 2;\n").unwrap();
+    git(&leaf, &["commit", "-qam", "1.2.0"]);
+    git(&leaf, &["tag", "v1.2.0"]);
+    assert_eq!(resolve(&project, &home), first);
+
+    fs::remove_dir_all(&root).unwrap();
+}